@@ -0,0 +1,22 @@
+#[cfg(any(feature = "health", feature = "reflection"))]
+extern crate tower_grpc_build;
+
+fn main() {
+    // Build the gRPC health checking service
+    #[cfg(feature = "health")]
+    tower_grpc_build::Config::new()
+        .enable_server(true)
+        .enable_client(true)
+        .build(&["proto/grpc/health/v1/health.proto"],
+               &["proto"])
+        .unwrap_or_else(|e| panic!("protobuf compilation failed: {}", e));
+
+    // Build the gRPC server reflection service
+    #[cfg(feature = "reflection")]
+    tower_grpc_build::Config::new()
+        .enable_server(true)
+        .enable_client(true)
+        .build(&["proto/grpc/reflection/v1alpha/reflection.proto"],
+               &["proto"])
+        .unwrap_or_else(|e| panic!("protobuf compilation failed: {}", e));
+}
@@ -0,0 +1,171 @@
+//! Cancellation of calls.
+
+use deadline::Timer;
+
+use futures::{Async, Future, Poll};
+use futures::task::{self, Task};
+
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+/// Cancels a call, from any task.
+///
+/// Canceling a client call fails its response future or response stream with
+/// `CANCELED`, and drops its half of the HTTP/2 stream, which resets it with
+/// `CANCEL`. Cancellation has no effect once the call has completed.
+///
+/// On the server, a request's handle is canceled when the call's stream
+/// fails, such as when the client resets it.
+#[derive(Debug, Clone)]
+pub struct CancelHandle {
+    shared: Arc<Shared>,
+}
+
+/// Resolves once a call is canceled or its deadline passes.
+///
+/// Returned by `Request::canceled`.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct Canceled {
+    cancel: Option<CancelHandle>,
+    deadline: Timer,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    canceled: AtomicBool,
+
+    /// Tasks to notify on cancellation.
+    tasks: Mutex<Vec<Task>>,
+}
+
+// ===== impl CancelHandle =====
+
+impl CancelHandle {
+    pub(crate) fn new() -> Self {
+        CancelHandle {
+            shared: Arc::new(Shared::default()),
+        }
+    }
+
+    /// Cancel the call.
+    pub fn cancel(&self) {
+        if self.shared.canceled.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let tasks = mem::replace(&mut *self.shared.tasks.lock().unwrap(), Vec::new());
+
+        for task in tasks {
+            task.notify();
+        }
+    }
+
+    /// Release the tasks waiting for cancellation, once the call has
+    /// completed and can no longer be canceled.
+    pub(crate) fn complete(&self) {
+        self.shared.tasks.lock().unwrap().clear();
+    }
+
+    /// Returns true if the call has been canceled.
+    pub fn is_canceled(&self) -> bool {
+        self.shared.canceled.load(Ordering::SeqCst)
+    }
+
+    /// Returns true if the call has been canceled.
+    ///
+    /// Otherwise, the current task is notified when it is.
+    pub(crate) fn poll_canceled(&self) -> bool {
+        if self.is_canceled() {
+            return true;
+        }
+
+        {
+            let mut tasks = self.shared.tasks.lock().unwrap();
+
+            if !tasks.iter().any(|task| task.will_notify_current()) {
+                tasks.push(task::current());
+            }
+        }
+
+        // The call may have been canceled before the task was registered.
+        self.is_canceled()
+    }
+
+    /// The number of tasks waiting for cancellation.
+    #[cfg(test)]
+    pub(crate) fn waiting_tasks(&self) -> usize {
+        self.shared.tasks.lock().unwrap().len()
+    }
+}
+
+// ===== impl Canceled =====
+
+impl Canceled {
+    pub(crate) fn new(cancel: Option<CancelHandle>, deadline: Option<Instant>) -> Self {
+        Canceled {
+            cancel,
+            deadline: Timer::new(deadline),
+        }
+    }
+}
+
+impl Future for Canceled {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        let canceled = self.cancel.as_ref()
+            .map(|cancel| cancel.poll_canceled())
+            .unwrap_or(false);
+
+        if canceled || self.deadline.poll_expired() {
+            Ok(Async::Ready(()))
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::future;
+
+    #[test]
+    fn cancel_notifies_waiting_tasks() {
+        let cancel = CancelHandle::new();
+        let mut canceled = Canceled::new(Some(cancel.clone()), None);
+
+        let canceled = future::lazy(move || {
+            assert!(canceled.poll().unwrap().is_not_ready());
+            Ok::<_, ()>(canceled)
+        }).wait().unwrap();
+        assert_eq!(cancel.shared.tasks.lock().unwrap().len(), 1);
+
+        cancel.cancel();
+        assert!(cancel.shared.tasks.lock().unwrap().is_empty());
+        assert_eq!(canceled.wait(), Ok(()));
+    }
+
+    #[test]
+    fn complete_releases_waiting_tasks() {
+        let cancel = CancelHandle::new();
+
+        let handle = cancel.clone();
+        future::lazy(move || {
+            assert!(!handle.poll_canceled());
+            // Polling again from the same task registers it once.
+            assert!(!handle.poll_canceled());
+            Ok::<_, ()>(())
+        }).wait().unwrap();
+        assert_eq!(cancel.shared.tasks.lock().unwrap().len(), 1);
+
+        cancel.complete();
+        assert!(cancel.shared.tasks.lock().unwrap().is_empty());
+        assert!(!cancel.is_canceled());
+    }
+}
@@ -2,35 +2,96 @@ pub mod unary;
 pub mod client_streaming;
 pub mod server_streaming;
 pub mod streaming;
+pub mod retry;
 
-use Status;
+use codec::Codec;
+use generic;
+use generic::compression::Compression;
+use web::Mode;
 
-use futures::{stream, Stream, Poll};
-use http::{uri, HeaderMap, Uri};
+use futures::{Stream, Poll};
+use http::uri;
 use prost::Message;
 use tower_h2::{HttpService, BoxBody};
 
-#[derive(Debug)]
+use std::fmt;
+
+pub use generic::client::Interceptor;
+pub use self::retry::{Retry, RetryPolicy};
+
+/// A protobuf gRPC client, backed by an HTTP/2.0 service.
+///
+/// Calls go through the generic client with a protobuf `Codec` for the
+/// message types of each method.
 pub struct Grpc<T> {
     /// The inner HTTP/2.0 service.
     inner: T,
-}
 
-/// Convert a stream of protobuf messages to an HTTP body payload.
-///
-/// TODO: Rename to `IntoEncode` or something...
-pub trait Encodable<T> {
-    fn into_encode(self) -> T;
+    /// Settings shared by every call, whatever its message types.
+    config: generic::client::Config,
 }
 
 // ===== impl Grpc =====
 
 impl<T> Grpc<T>
-where T: HttpService,
+where T: HttpService<RequestBody = BoxBody>,
 {
     /// Create a new `Grpc` instance backed by the given HTTP service.
     pub fn new(inner: T) -> Self {
-        Grpc { inner }
+        Grpc {
+            inner,
+            config: generic::client::Config::new(),
+        }
+    }
+
+    /// Create a new `Grpc` instance that passes every request through
+    /// `interceptor`.
+    pub fn with_interceptor<I>(inner: T, interceptor: I) -> Self
+    where I: Interceptor + 'static,
+    {
+        let mut grpc = Grpc::new(inner);
+        grpc.set_interceptor(interceptor);
+        grpc
+    }
+
+    /// Pass every request through `interceptor`, replacing any previous one.
+    pub fn set_interceptor<I>(&mut self, interceptor: I) -> &mut Self
+    where I: Interceptor + 'static,
+    {
+        self.config.set_interceptor(interceptor);
+        self
+    }
+
+    /// Set the compression algorithms used for requests and responses.
+    pub fn set_compression(&mut self, compression: Compression) -> &mut Self {
+        self.config.set_compression(compression);
+        self
+    }
+
+    /// Limit the size of response messages, in bytes.
+    ///
+    /// Larger responses fail with `RESOURCE_EXHAUSTED` before being
+    /// buffered. Defaults to 4 MiB; `None` removes the limit.
+    pub fn set_max_decoding_message_size(&mut self, limit: Option<usize>) -> &mut Self {
+        self.config.set_max_decoding_message_size(limit);
+        self
+    }
+
+    /// Limit the size of request messages, in bytes.
+    ///
+    /// Larger requests fail with `RESOURCE_EXHAUSTED`. Unlimited by default.
+    pub fn set_max_encoding_message_size(&mut self, limit: Option<usize>) -> &mut Self {
+        self.config.set_max_encoding_message_size(limit);
+        self
+    }
+
+    /// Speak gRPC-Web in the given mode, or gRPC with `None`.
+    ///
+    /// For servers that are only reachable through a gRPC-Web proxy, or
+    /// over connections that cannot carry trailers.
+    pub fn set_web_mode(&mut self, mode: Option<Mode>) -> &mut Self {
+        self.config.set_web_mode(mode);
+        self
     }
 
     pub fn poll_ready(&mut self) -> Poll<(), ::Error<T::Error>> {
@@ -42,34 +103,34 @@ where T: HttpService,
                          request: ::Request<M1>,
                          path: uri::PathAndQuery)
         -> unary::ResponseFuture<M2, T::Future, T::ResponseBody>
-    where unary::Once<M1>: Encodable<T::RequestBody>,
+    where M1: Message + Send + 'static,
+          M2: Message + Default,
     {
-        let request = request.map(|v| stream::once(Ok(v)));
-        let response = self.client_streaming(request, path);
-
-        unary::ResponseFuture::new(response)
+        let mut codec = Codec::<M1, M2>::new();
+        self.config.unary(&mut codec, &mut self.inner, request, path)
     }
 
-    pub fn client_streaming<B, M>(&mut self,
-                                  request: ::Request<B>,
-                                  path: uri::PathAndQuery)
-        -> client_streaming::ResponseFuture<M, T::Future, T::ResponseBody>
-    where B: Encodable<T::RequestBody>,
+    pub fn client_streaming<B, M1, M2>(&mut self,
+                                       request: ::Request<B>,
+                                       path: uri::PathAndQuery)
+        -> client_streaming::ResponseFuture<M2, T::Future, T::ResponseBody>
+    where B: Stream<Item = M1, Error = ::Error> + Send + 'static,
+          M1: Message + Send + 'static,
+          M2: Message + Default,
     {
-        let response = self.streaming(request, path);
-        client_streaming::ResponseFuture::new(response)
+        let mut codec = Codec::<M1, M2>::new();
+        self.config.client_streaming(&mut codec, &mut self.inner, request, path)
     }
 
     pub fn server_streaming<M1, M2>(&mut self,
                                     request: ::Request<M1>,
                                     path: uri::PathAndQuery)
         -> server_streaming::ResponseFuture<M2, T::Future>
-    where unary::Once<M1>: Encodable<T::RequestBody>,
+    where M1: Message + Send + 'static,
+          M2: Message + Default,
     {
-        let request = request.map(|v| stream::once(Ok(v)));
-        let response = self.streaming(request, path);
-
-        server_streaming::ResponseFuture::new(response)
+        let mut codec = Codec::<M1, M2>::new();
+        self.config.server_streaming(&mut codec, &mut self.inner, request, path)
     }
 
     /// Initiate a full streaming gRPC request
@@ -77,70 +138,103 @@ where T: HttpService,
     /// # Generics
     ///
     /// **B**: The request stream of gRPC message values.
-    /// **M**: The response **message** (not stream) type.
-    pub fn streaming<B, M>(&mut self,
-                           request: ::Request<B>,
-                           path: uri::PathAndQuery)
-        -> streaming::ResponseFuture<M, T::Future>
-    where B: Encodable<T::RequestBody>,
+    /// **M1**: The request **message** type.
+    /// **M2**: The response **message** (not stream) type.
+    pub fn streaming<B, M1, M2>(&mut self,
+                                request: ::Request<B>,
+                                path: uri::PathAndQuery)
+        -> streaming::ResponseFuture<M2, T::Future>
+    where B: Stream<Item = M1, Error = ::Error> + Send + 'static,
+          M1: Message + Send + 'static,
+          M2: Message + Default,
     {
-        use http::header::{self, HeaderValue};
-
-        // TODO: validate the path
-
-        // Get the gRPC's method URI
-        let mut parts = uri::Parts::default();
-        parts.path_and_query = Some(path);
-
-        // Get the URI;
-        let uri = match Uri::from_parts(parts) {
-            Ok(uri) => uri,
-            Err(_) => unimplemented!(),
-        };
-
-        // Convert the request body
-        let request = request.map(Encodable::into_encode);
-
-        // Convert to an HTTP request
-        let mut request = request.into_http(uri);
-
-        // Add the gRPC related HTTP headers
-        request.headers_mut()
-            .insert(header::TE, HeaderValue::from_static("trailers"));
-
-        // Set the content type
-        // TODO: Don't hard code this here
-        let content_type = "application/grpc+proto";
-        request.headers_mut().insert(
-            header::CONTENT_TYPE,
-            HeaderValue::from_static(content_type));
-
-        // Call the inner HTTP service
-        let response = self.inner.call(request);
-
-        streaming::ResponseFuture::new(response)
+        let mut codec = Codec::<M1, M2>::new();
+        self.config.streaming(&mut codec, &mut self.inner, request, path)
     }
 }
 
-// ===== impl Encodable =====
-
-impl<T, U> Encodable<BoxBody> for T
-where T: Stream<Item = U, Error = ::Error> + Send + 'static,
-      U: Message + 'static,
+impl<T> Grpc<Retry<T>>
+where T: HttpService<RequestBody = BoxBody> + Clone,
+      T::Error: 'static,
 {
-    fn into_encode(self) -> BoxBody {
-        use codec::Encoder;
-        use generic::Encode;
+    /// Create a new `Grpc` instance that retries failed calls as allowed by
+    /// `policy`.
+    ///
+    /// Generated clients can retry calls the same way, when created with a
+    /// `Retry` service.
+    pub fn with_retry_policy(inner: T, policy: RetryPolicy) -> Self {
+        Grpc::new(Retry::new(inner, policy))
+    }
+}
 
-        let encode = Encode::new(Encoder::new(), self, false);
-        BoxBody::new(Box::new(encode))
+impl<T> fmt::Debug for Grpc<T>
+where T: fmt::Debug,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Grpc")
+            .field("inner", &self.inner)
+            .field("config", &self.config)
+            .finish()
     }
 }
 
-// ===== utility fns =====
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Code, Request};
+
+    use futures::{future, Future};
+    use http;
+    use tower::Service;
+    use tower_h2::RecvBody;
+
+    /// An HTTP service that must never be called.
+    #[derive(Debug)]
+    struct NeverCalled;
+
+    impl Service for NeverCalled {
+        type Request = http::Request<BoxBody>;
+        type Response = http::Response<RecvBody>;
+        type Error = ();
+        type Future = future::Empty<Self::Response, ()>;
+
+        fn poll_ready(&mut self) -> Poll<(), ()> {
+            Ok(().into())
+        }
+
+        fn call(&mut self, _: Self::Request) -> Self::Future {
+            panic!("request with an invalid path was sent");
+        }
+    }
 
-fn check_grpc_status(trailers: &HeaderMap) -> Option<Status> {
-    trailers.get("grpc-status").map(|s| {
-        Status::from_bytes(s.as_ref())
-    })
+    #[derive(Clone, PartialEq, Message)]
+    struct Ping {
+        #[prost(string, tag="1")]
+        name: String,
+    }
+
+    #[test]
+    fn invalid_path_is_an_error() {
+        let mut grpc = Grpc::new(NeverCalled);
+
+        let paths = [
+            "/",
+            "/pkg.Service",
+            "//Method",
+            "/pkg.Service/",
+            "/pkg.Service/Method/extra",
+            "/pkg.Service/Method?query",
+        ];
+
+        for &path in paths.iter() {
+            let path = uri::PathAndQuery::from_static(path);
+            let response: unary::ResponseFuture<Ping, _, _> =
+                grpc.unary(Request::new(Ping::default()), path);
+
+            match response.wait() {
+                Err(::Error::Grpc(status, _)) => assert_eq!(status.code(), Code::INTERNAL),
+                res => panic!("unexpected result: {:?}", res),
+            }
+        }
+    }
 }
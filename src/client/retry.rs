@@ -0,0 +1,966 @@
+//! Automatic retries of failed calls.
+//!
+//! A call is retried when it fails before the server sends response headers,
+//! either because the request could not be sent, or because the server
+//! answered with a trailers-only response carrying a retryable status. Failed
+//! attempts get the status a client would see for them, from `grpc-status`,
+//! the HTTP status, or the HTTP/2 error. Once response headers arrive, the
+//! call is committed to that attempt.
+
+use {Code, Status};
+use cancel::CancelHandle;
+use deadline::{self, Deadline};
+
+use bytes::Bytes;
+use futures::{Async, Future, Poll};
+use {h2, http, tower_h2};
+use http::{HeaderMap, StatusCode};
+use http::header::HeaderValue;
+use tokio_timer::Delay;
+use tower::Service;
+use tower_h2::{Body, BoxBody, HttpService};
+
+use std::any::Any;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The number of attempts that preceded a retry.
+const PREVIOUS_ATTEMPTS_HEADER: &str = "grpc-previous-rpc-attempts";
+
+/// The default limit of request data buffered for retries: 1 MiB.
+const DEFAULT_MAX_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// Decides which failed calls are retried, and when.
+///
+/// The delay before retry `n` is chosen at random between zero and
+/// `initial_backoff * backoff_multiplier^(n - 1)`, capped at `max_backoff`.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// The most attempts made for a call, including the first.
+    max_attempts: usize,
+
+    initial_backoff: Duration,
+
+    max_backoff: Duration,
+
+    backoff_multiplier: f64,
+
+    /// Status codes of failed attempts that can be retried.
+    retryable_codes: Vec<Code>,
+
+    /// The most request data buffered for replaying, in bytes.
+    max_buffer_size: usize,
+}
+
+/// Retries the calls sent through an HTTP/2.0 service.
+///
+/// Calls are sent again through clones of the inner service.
+#[derive(Debug, Clone)]
+pub struct Retry<T> {
+    inner: T,
+    policy: RetryPolicy,
+}
+
+/// Response future of `Retry`, resolving once a call is committed.
+pub struct ResponseFuture<T>
+where T: HttpService,
+{
+    /// Sends the attempts after the first.
+    service: T,
+
+    policy: RetryPolicy,
+
+    /// The request head, sent again on each attempt.
+    head: Head,
+
+    /// The request body, shared by the attempts.
+    body: Arc<Mutex<Replay>>,
+
+    /// The number of attempts made, including those that failed before
+    /// being sent, limited by the policy.
+    attempts: usize,
+
+    /// The number of attempts sent, reported to the server on retries.
+    sent: usize,
+
+    deadline: Option<Instant>,
+
+    cancel: Option<CancelHandle>,
+
+    state: State<T::Future>,
+}
+
+#[derive(Debug)]
+struct Head {
+    method: http::Method,
+    uri: http::Uri,
+    version: http::Version,
+    headers: HeaderMap,
+}
+
+#[derive(Debug)]
+enum State<F> {
+    /// Waiting for the response headers of an attempt.
+    Attempt(F),
+
+    /// Waiting before the next attempt.
+    Backoff(Delay),
+
+    /// Waiting for the service to accept the next attempt.
+    Ready,
+}
+
+/// What to do after polling the current state.
+enum Next<T> {
+    Respond(T),
+    Ready,
+    Send,
+}
+
+/// A request body read by each attempt of a call in turn.
+struct Replay {
+    /// The original body, read by the latest attempt once the buffered data
+    /// has been sent again.
+    inner: BoxBody,
+
+    /// Data read from `inner`, in order.
+    buffered: Vec<Bytes>,
+
+    buffered_len: usize,
+
+    max_buffer_size: usize,
+
+    /// False once data read from `inner` is no longer buffered, because the
+    /// buffer limit was exceeded or the call was committed.
+    buffering: bool,
+
+    /// The number of buffered chunks the latest attempt has sent.
+    replayed: usize,
+
+    /// Set once `inner` has no more data.
+    data_done: bool,
+
+    /// The trailers of `inner`, once read.
+    trailers: Option<Option<HeaderMap>>,
+
+    /// The only attempt still sending the body.
+    attempt: usize,
+}
+
+/// The body sent by one attempt.
+struct ReplayBody {
+    replay: Arc<Mutex<Replay>>,
+    attempt: usize,
+}
+
+// ===== impl RetryPolicy =====
+
+impl RetryPolicy {
+    /// Make at most `max_attempts` attempts per call, including the first.
+    ///
+    /// Calls failing with `UNAVAILABLE` are retried after an initial backoff
+    /// of 100 milliseconds, doubling up to 5 seconds, as long as at most
+    /// 1 MiB of request data has been sent.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_attempts` is zero.
+    pub fn new(max_attempts: usize) -> Self {
+        assert!(max_attempts > 0, "a call must be attempted at least once");
+
+        RetryPolicy {
+            max_attempts,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            backoff_multiplier: 2.0,
+            retryable_codes: vec![Code::UNAVAILABLE],
+            max_buffer_size: DEFAULT_MAX_BUFFER_SIZE,
+        }
+    }
+
+    /// Set the largest delay before the first retry.
+    pub fn set_initial_backoff(&mut self, backoff: Duration) -> &mut Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Set the largest delay before any retry.
+    pub fn set_max_backoff(&mut self, backoff: Duration) -> &mut Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Set the factor by which the largest delay grows after each retry.
+    pub fn set_backoff_multiplier(&mut self, multiplier: f64) -> &mut Self {
+        self.backoff_multiplier = multiplier;
+        self
+    }
+
+    /// Set the status codes that can be retried.
+    ///
+    /// An HTTP/2 error counts as the status it maps to, such as
+    /// `UNAVAILABLE` for a refused stream or a failed connection. Other
+    /// errors of the inner service count as `UNAVAILABLE`.
+    pub fn set_retryable_codes<I>(&mut self, codes: I) -> &mut Self
+    where I: IntoIterator<Item = Code>,
+    {
+        self.retryable_codes = codes.into_iter().collect();
+        self
+    }
+
+    /// Limit the request data buffered for retries, in bytes.
+    ///
+    /// A call that sent more than this is not retried.
+    pub fn set_max_buffer_size(&mut self, limit: usize) -> &mut Self {
+        self.max_buffer_size = limit;
+        self
+    }
+
+    /// A random delay before the retry following attempt number `attempts`.
+    fn backoff(&self, attempts: usize) -> Duration {
+        let initial = as_secs_f64(self.initial_backoff);
+        let max = as_secs_f64(self.max_backoff);
+
+        let backoff = initial * self.backoff_multiplier.powi(attempts as i32 - 1);
+        let backoff = if backoff < max { backoff } else { max };
+
+        from_secs_f64(backoff * random())
+    }
+}
+
+// ===== impl Retry =====
+
+impl<T> Retry<T> {
+    /// Retry the calls sent through `inner` as allowed by `policy`.
+    pub fn new(inner: T, policy: RetryPolicy) -> Self {
+        Retry { inner, policy }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T> Service for Retry<T>
+where T: HttpService<RequestBody = BoxBody> + Clone,
+      T::Error: 'static,
+{
+    type Request = http::Request<BoxBody>;
+    type Response = http::Response<T::ResponseBody>;
+    type Error = T::Error;
+    type Future = ResponseFuture<T>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, request: Self::Request) -> Self::Future {
+        let (parts, body) = request.into_parts();
+
+        let head = Head {
+            method: parts.method.clone(),
+            uri: parts.uri.clone(),
+            version: parts.version,
+            headers: parts.headers.clone(),
+        };
+
+        let deadline = parts.extensions.get::<Deadline>().map(|deadline| deadline.0);
+        let cancel = parts.extensions.get::<CancelHandle>().cloned();
+
+        // Nothing needs to be buffered for a call that is never retried.
+        let max_buffer_size = if self.policy.max_attempts > 1 {
+            self.policy.max_buffer_size
+        } else {
+            0
+        };
+
+        let body = Arc::new(Mutex::new(Replay::new(body, max_buffer_size)));
+
+        let request = http::Request::from_parts(parts, ReplayBody::new(&body));
+        let response = self.inner.call(request);
+
+        ResponseFuture {
+            service: self.inner.clone(),
+            policy: self.policy.clone(),
+            head,
+            body,
+            attempts: 1,
+            sent: 1,
+            deadline,
+            cancel,
+            state: State::Attempt(response),
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<T> ResponseFuture<T>
+where T: HttpService<RequestBody = BoxBody>,
+{
+    /// The delay before retrying an attempt that failed with `code`, if it
+    /// can be retried.
+    fn retry_backoff(&self, code: Code) -> Option<Duration> {
+        if self.attempts >= self.policy.max_attempts ||
+            !self.policy.retryable_codes.contains(&code)
+        {
+            return None;
+        }
+
+        if !self.body.lock().unwrap().buffering {
+            debug!("request body too large to retry; code={:?}", code);
+            return None;
+        }
+
+        if self.cancel.as_ref().map(|cancel| cancel.is_canceled()).unwrap_or(false) {
+            return None;
+        }
+
+        let backoff = self.policy.backoff(self.attempts);
+
+        // There is no point retrying once the call has failed anyway.
+        if let Some(deadline) = self.deadline {
+            if Instant::now() + backoff >= deadline {
+                debug!("not retrying past the deadline; code={:?}", code);
+                return None;
+            }
+        }
+
+        Some(backoff)
+    }
+
+    /// Send the request again.
+    fn send(&mut self) -> T::Future {
+        self.body.lock().unwrap().retry();
+
+        let mut request = http::Request::new(ReplayBody::new(&self.body));
+        *request.method_mut() = self.head.method.clone();
+        *request.uri_mut() = self.head.uri.clone();
+        *request.version_mut() = self.head.version;
+        *request.headers_mut() = self.head.headers.clone();
+
+        request.headers_mut()
+            .insert(PREVIOUS_ATTEMPTS_HEADER, HeaderValue::from(self.sent));
+
+        // Propagate the time remaining before the deadline, now shorter.
+        if let Some(deadline) = self.deadline {
+            request.headers_mut().insert(
+                deadline::TIMEOUT_HEADER,
+                deadline::encode_deadline(deadline));
+        }
+
+        self.attempts += 1;
+        self.sent += 1;
+        self.service.call(request)
+    }
+}
+
+impl<T> Future for ResponseFuture<T>
+where T: HttpService<RequestBody = BoxBody>,
+      T::Error: 'static,
+{
+    type Item = http::Response<T::ResponseBody>;
+    type Error = T::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let next = match self.state {
+                State::Attempt(ref mut response) => match response.poll() {
+                    Ok(Async::Ready(response)) => Next::Respond(Ok(response)),
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Err(e) => Next::Respond(Err(e)),
+                },
+                State::Backoff(ref mut delay) => {
+                    match delay.poll() {
+                        Ok(Async::Ready(())) => {}
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Err(e) => warn!("retry backoff timer failed; err={}", e),
+                    }
+
+                    Next::Ready
+                }
+                State::Ready => match self.service.poll_ready() {
+                    Ok(Async::Ready(())) => Next::Send,
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Err(e) => {
+                        // The attempt failed without being sent.
+                        self.attempts += 1;
+                        Next::Respond(Err(e))
+                    }
+                },
+            };
+
+            let result = match next {
+                Next::Respond(result) => result,
+                Next::Ready => {
+                    self.state = State::Ready;
+                    continue;
+                }
+                Next::Send => {
+                    self.state = State::Attempt(self.send());
+                    continue;
+                }
+            };
+
+            let code = match result {
+                Ok(ref response) => response_code(response),
+                Err(ref e) => Some(error_code(e)),
+            };
+
+            match code.and_then(|code| self.retry_backoff(code).map(|backoff| (code, backoff))) {
+                Some((code, backoff)) => {
+                    debug!("retrying call; attempts={}, code={:?}, backoff={:?}",
+                           self.attempts, code, backoff);
+
+                    self.state = if backoff == Duration::from_secs(0) {
+                        State::Ready
+                    } else {
+                        State::Backoff(Delay::new(Instant::now() + backoff))
+                    };
+                }
+                None => {
+                    self.body.lock().unwrap().commit();
+
+                    return result.map(Async::Ready);
+                }
+            }
+        }
+    }
+}
+
+impl<T> fmt::Debug for ResponseFuture<T>
+where T: HttpService + fmt::Debug,
+      T::Future: fmt::Debug,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("retry::ResponseFuture")
+            .field("service", &self.service)
+            .field("policy", &self.policy)
+            .field("head", &self.head)
+            .field("attempts", &self.attempts)
+            .field("sent", &self.sent)
+            .field("deadline", &self.deadline)
+            .field("cancel", &self.cancel)
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+// ===== impl Replay =====
+
+impl Replay {
+    fn new(inner: BoxBody, max_buffer_size: usize) -> Self {
+        Replay {
+            inner,
+            buffered: Vec::new(),
+            buffered_len: 0,
+            max_buffer_size,
+            buffering: true,
+            replayed: 0,
+            data_done: false,
+            trailers: None,
+            attempt: 1,
+        }
+    }
+
+    /// Buffer `data`, just sent by the latest attempt, for later attempts.
+    fn buffer(&mut self, data: &Bytes) {
+        if !self.buffering {
+            return;
+        }
+
+        if self.buffered_len + data.len() > self.max_buffer_size {
+            trace!("request body exceeds the retry buffer; limit={}", self.max_buffer_size);
+
+            self.buffering = false;
+            self.release();
+            return;
+        }
+
+        self.buffered_len += data.len();
+        self.buffered.push(data.clone());
+        self.replayed += 1;
+    }
+
+    /// Start sending the body again, for the next attempt.
+    fn retry(&mut self) {
+        self.attempt += 1;
+        self.replayed = 0;
+    }
+
+    /// Stop buffering, as the latest attempt is the last one.
+    fn commit(&mut self) {
+        self.buffering = false;
+        self.release();
+    }
+
+    /// Free the buffered data once no attempt needs it: no more data is
+    /// buffered, and the latest attempt has sent all of it again.
+    fn release(&mut self) {
+        if !self.buffering && self.replayed >= self.buffered.len() {
+            self.buffered = Vec::new();
+            self.buffered_len = 0;
+            self.replayed = 0;
+        }
+    }
+}
+
+// ===== impl ReplayBody =====
+
+impl ReplayBody {
+    /// The body of the latest attempt.
+    fn new(replay: &Arc<Mutex<Replay>>) -> BoxBody {
+        let attempt = replay.lock().unwrap().attempt;
+
+        BoxBody::new(Box::new(ReplayBody {
+            replay: replay.clone(),
+            attempt,
+        }))
+    }
+}
+
+impl Body for ReplayBody {
+    type Data = Bytes;
+
+    fn is_end_stream(&self) -> bool {
+        false
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Bytes>, h2::Error> {
+        let mut replay = self.replay.lock().unwrap();
+
+        // A superseded attempt resets its stream.
+        if replay.attempt != self.attempt {
+            return Err(h2::Reason::CANCEL.into());
+        }
+
+        let next = replay.buffered.get(replay.replayed).cloned();
+        if let Some(data) = next {
+            replay.replayed += 1;
+
+            // A committed attempt may have been replaying the buffer.
+            replay.release();
+
+            return Ok(Some(data).into());
+        }
+
+        if replay.data_done {
+            return Ok(None.into());
+        }
+
+        match try_ready!(replay.inner.poll_data()) {
+            Some(data) => {
+                replay.buffer(&data);
+                Ok(Some(data).into())
+            }
+            None => {
+                replay.data_done = true;
+                Ok(None.into())
+            }
+        }
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, h2::Error> {
+        let mut replay = self.replay.lock().unwrap();
+
+        if replay.attempt != self.attempt {
+            return Err(h2::Reason::CANCEL.into());
+        }
+
+        if let Some(ref trailers) = replay.trailers {
+            return Ok(trailers.clone().into());
+        }
+
+        let trailers = try_ready!(replay.inner.poll_trailers());
+        replay.trailers = Some(trailers.clone());
+
+        Ok(trailers.into())
+    }
+}
+
+// ===== utility fns =====
+
+/// The status code of a failed call, if `response` is one.
+///
+/// Classified as the client does: a trailers-only response carries the
+/// status in its headers, and a response without one that did not come from
+/// a gRPC server, such as an error generated by a proxy, by its HTTP status.
+fn response_code<B>(response: &http::Response<B>) -> Option<Code> {
+    if let Some(status) = Status::from_header_map(response.headers()) {
+        return Some(status.code());
+    }
+
+    if response.status() != StatusCode::OK {
+        return Some(Status::from_http_status(response.status()).code());
+    }
+
+    None
+}
+
+/// The status code of an attempt that failed with `err`.
+///
+/// HTTP/2 errors are classified by their reason, so a stream reset with
+/// `INTERNAL_ERROR` is not retried like one the server refused.
+fn error_code<E: 'static>(err: &E) -> Code {
+    let err: &dyn Any = err;
+
+    if let Some(err) = err.downcast_ref::<h2::Error>() {
+        return Status::from_h2_error(err).code();
+    }
+
+    if let Some(err) = err.downcast_ref::<tower_h2::client::Error>() {
+        return match err.reason() {
+            Some(reason) => Status::from_h2_error(&h2::Error::from(reason)).code(),
+            // The connection failed, or the request could not be sent.
+            None => Code::UNAVAILABLE,
+        };
+    }
+
+    Code::UNAVAILABLE
+}
+
+fn as_secs_f64(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1e9
+}
+
+fn from_secs_f64(secs: f64) -> Duration {
+    Duration::new(secs.trunc() as u64, (secs.fract() * 1e9) as u32)
+}
+
+/// A random number in `[0, 1)`, to jitter backoffs.
+fn random() -> f64 {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+
+    // Every `RandomState` is keyed differently, which is random enough for
+    // spreading out retries.
+    let bits = RandomState::new().build_hasher().finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::TestBody;
+
+    use futures::future;
+
+    type Requests = Arc<Mutex<Vec<(HeaderMap, Vec<Bytes>)>>>;
+
+    /// Answers the first `failures` calls with a trailers-only response of
+    /// status `code`, or an HTTP error, recording every request.
+    #[derive(Debug, Clone)]
+    struct Flaky {
+        failures: usize,
+        code: Option<&'static str>,
+        http_status: StatusCode,
+        requests: Requests,
+
+        /// The number of times `poll_ready` fails before succeeding.
+        unready: Arc<Mutex<usize>>,
+    }
+
+    impl Flaky {
+        fn new(failures: usize, code: &'static str) -> Self {
+            Flaky {
+                failures,
+                code: Some(code),
+                http_status: StatusCode::OK,
+                requests: Requests::default(),
+                unready: Arc::default(),
+            }
+        }
+
+        /// Fails with `http_status` and no `grpc-status`, as a proxy may.
+        fn http(failures: usize, http_status: StatusCode) -> Self {
+            Flaky {
+                code: None,
+                http_status,
+                ..Flaky::new(failures, "0")
+            }
+        }
+    }
+
+    impl Service for Flaky {
+        type Request = http::Request<BoxBody>;
+        type Response = http::Response<TestBody>;
+        type Error = ();
+        type Future = future::FutureResult<Self::Response, ()>;
+
+        fn poll_ready(&mut self) -> Poll<(), ()> {
+            let mut unready = self.unready.lock().unwrap();
+            if *unready > 0 {
+                *unready -= 1;
+                return Err(());
+            }
+
+            Ok(().into())
+        }
+
+        fn call(&mut self, request: Self::Request) -> Self::Future {
+            let (head, mut body) = request.into_parts();
+
+            let mut chunks = Vec::new();
+            while let Async::Ready(Some(chunk)) = body.poll_data().expect("poll_data") {
+                chunks.push(chunk);
+            }
+
+            let mut requests = self.requests.lock().unwrap();
+            requests.push((head.headers, chunks));
+
+            let mut response = http::Response::new(TestBody::default());
+            if requests.len() <= self.failures {
+                *response.status_mut() = self.http_status;
+
+                if let Some(code) = self.code {
+                    response.headers_mut().insert("grpc-status", HeaderValue::from_static(code));
+                }
+            }
+
+            future::ok(response)
+        }
+    }
+
+    /// Fails the first `resets` calls with an HTTP/2 error of `reason`.
+    #[derive(Debug, Clone)]
+    struct Resetting {
+        resets: usize,
+        reason: h2::Reason,
+        calls: Arc<Mutex<usize>>,
+    }
+
+    impl Service for Resetting {
+        type Request = http::Request<BoxBody>;
+        type Response = http::Response<TestBody>;
+        type Error = h2::Error;
+        type Future = future::FutureResult<Self::Response, h2::Error>;
+
+        fn poll_ready(&mut self) -> Poll<(), h2::Error> {
+            Ok(().into())
+        }
+
+        fn call(&mut self, _: Self::Request) -> Self::Future {
+            let mut calls = self.calls.lock().unwrap();
+            *calls += 1;
+
+            if *calls <= self.resets {
+                future::err(self.reason.into())
+            } else {
+                future::ok(http::Response::new(TestBody::default()))
+            }
+        }
+    }
+
+    /// A policy of `max_attempts` attempts, retrying without delay.
+    fn policy(max_attempts: usize) -> RetryPolicy {
+        let mut policy = RetryPolicy::new(max_attempts);
+        policy.set_initial_backoff(Duration::from_secs(0));
+        policy
+    }
+
+    /// A request of one chunk.
+    fn request() -> http::Request<BoxBody> {
+        let body = TestBody::new(vec![Bytes::from_static(b"hello")]);
+        http::Request::new(BoxBody::new(Box::new(body)))
+    }
+
+    /// Send a request of one chunk through `Flaky`, returning the status of
+    /// the response, if any, and the requests received.
+    fn call(policy: RetryPolicy, failures: usize, code: &'static str)
+        -> (Option<HeaderValue>, Vec<(HeaderMap, Vec<Bytes>)>)
+    {
+        let (response, requests) = send(policy, Flaky::new(failures, code));
+        (response.headers().get("grpc-status").cloned(), requests)
+    }
+
+    /// Send a request of one chunk through `flaky`, returning the response
+    /// and the requests received.
+    fn send(policy: RetryPolicy, flaky: Flaky)
+        -> (http::Response<TestBody>, Vec<(HeaderMap, Vec<Bytes>)>)
+    {
+        let requests = flaky.requests.clone();
+
+        let mut retry = Retry::new(flaky, policy);
+        let response = Service::call(&mut retry, request())
+            .wait()
+            .expect("response");
+
+        let requests = requests.lock().unwrap().drain(..).collect();
+
+        (response, requests)
+    }
+
+    /// Send a request through `Resetting`, returning the result and the
+    /// number of calls made.
+    fn reset(resets: usize, reason: h2::Reason)
+        -> (Result<http::Response<TestBody>, h2::Error>, usize)
+    {
+        let calls = Arc::new(Mutex::new(0));
+        let resetting = Resetting { resets, reason, calls: calls.clone() };
+
+        let mut retry = Retry::new(resetting, policy(3));
+        let result = Service::call(&mut retry, request()).wait();
+
+        let calls = *calls.lock().unwrap();
+        (result, calls)
+    }
+
+    /// Poll `body` until it ends, returning its data.
+    fn read(body: &mut BoxBody) -> Vec<Bytes> {
+        let mut chunks = Vec::new();
+        while let Async::Ready(Some(chunk)) = body.poll_data().expect("poll_data") {
+            chunks.push(chunk);
+        }
+        chunks
+    }
+
+    #[test]
+    fn retries_with_the_same_body() {
+        let (status, requests) = call(policy(3), 1, "14");
+
+        assert_eq!(status, None);
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].0.get(PREVIOUS_ATTEMPTS_HEADER), None);
+        assert_eq!(requests[1].0.get(PREVIOUS_ATTEMPTS_HEADER),
+                   Some(&HeaderValue::from_static("1")));
+        assert_eq!(requests[0].1, vec![Bytes::from_static(b"hello")]);
+        assert_eq!(requests[1].1, requests[0].1);
+    }
+
+    #[test]
+    fn stops_after_max_attempts() {
+        let (status, requests) = call(policy(3), 5, "14");
+
+        assert_eq!(status, Some(HeaderValue::from_static("14")));
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[2].0.get(PREVIOUS_ATTEMPTS_HEADER),
+                   Some(&HeaderValue::from_static("2")));
+    }
+
+    #[test]
+    fn does_not_retry_other_codes() {
+        let (status, requests) = call(policy(3), 1, "13");
+
+        assert_eq!(status, Some(HeaderValue::from_static("13")));
+        assert_eq!(requests.len(), 1);
+    }
+
+    #[test]
+    fn retries_http_errors_by_their_status() {
+        // A proxy's 503 is `UNAVAILABLE`.
+        let (response, requests) = send(policy(3), Flaky::http(1, StatusCode::SERVICE_UNAVAILABLE));
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(requests.len(), 2);
+
+        // A 500 is `UNKNOWN`.
+        let (response, requests) = send(policy(3), Flaky::http(1, StatusCode::INTERNAL_SERVER_ERROR));
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(requests.len(), 1);
+    }
+
+    #[test]
+    fn retries_http2_errors_by_their_reason() {
+        let (result, calls) = reset(1, h2::Reason::REFUSED_STREAM);
+        assert!(result.is_ok());
+        assert_eq!(calls, 2);
+
+        let (result, calls) = reset(1, h2::Reason::INTERNAL_ERROR);
+        let err = result.err().expect("error");
+        assert_eq!(err.reason(), Some(h2::Reason::INTERNAL_ERROR));
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn does_not_retry_past_the_buffer_limit() {
+        let mut policy = policy(3);
+        policy.set_max_buffer_size(4);
+
+        let (status, requests) = call(policy, 1, "14");
+
+        assert_eq!(status, Some(HeaderValue::from_static("14")));
+        assert_eq!(requests.len(), 1);
+    }
+
+    #[test]
+    fn failed_poll_ready_is_retried() {
+        let flaky = Flaky::new(1, "14");
+        let requests = flaky.requests.clone();
+        *flaky.unready.lock().unwrap() = 1;
+
+        let mut retry = Retry::new(flaky, policy(3));
+        let response = Service::call(&mut retry, request())
+            .wait()
+            .expect("response");
+
+        assert_eq!(response.headers().get("grpc-status"), None);
+
+        // The server is only told of the attempt it received.
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].0.get(PREVIOUS_ATTEMPTS_HEADER),
+                   Some(&HeaderValue::from_static("1")));
+        assert_eq!(requests[1].1, vec![Bytes::from_static(b"hello")]);
+    }
+
+    #[test]
+    fn failed_poll_ready_uses_up_attempts() {
+        let flaky = Flaky::new(1, "14");
+        let requests = flaky.requests.clone();
+        *flaky.unready.lock().unwrap() = 5;
+
+        let mut retry = Retry::new(flaky, policy(3));
+        let response = Service::call(&mut retry, request()).wait();
+
+        assert_eq!(response.map(|_| ()), Err(()));
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn commit_frees_the_buffer() {
+        let mut retry = Retry::new(Flaky::new(1, "14"), policy(3));
+        let mut response = Service::call(&mut retry, request());
+
+        assert!(response.poll().expect("response").is_ready());
+        assert!(response.body.lock().unwrap().buffered.is_empty());
+    }
+
+    #[test]
+    fn commit_frees_the_buffer_once_replayed() {
+        let chunks = vec![Bytes::from_static(b"hello"), Bytes::from_static(b"world")];
+        let body = BoxBody::new(Box::new(TestBody::new(chunks.clone())));
+        let replay = Arc::new(Mutex::new(Replay::new(body, 1024)));
+
+        assert_eq!(read(&mut ReplayBody::new(&replay)), chunks);
+
+        replay.lock().unwrap().retry();
+        let mut body = ReplayBody::new(&replay);
+        assert_eq!(body.poll_data().expect("poll_data"), Async::Ready(Some(chunks[0].clone())));
+
+        // The committed attempt has yet to send the rest of the buffer.
+        replay.lock().unwrap().commit();
+        assert_eq!(replay.lock().unwrap().buffered.len(), 2);
+
+        assert_eq!(read(&mut body), vec![chunks[1].clone()]);
+        assert!(replay.lock().unwrap().buffered.is_empty());
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let mut policy = RetryPolicy::new(10);
+        policy.set_initial_backoff(Duration::from_millis(100));
+        policy.set_max_backoff(Duration::from_millis(300));
+
+        for attempts in 1..10 {
+            assert!(policy.backoff(attempts) < Duration::from_millis(300));
+        }
+
+        assert!(policy.backoff(1) < Duration::from_millis(100));
+    }
+}
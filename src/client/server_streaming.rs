@@ -1,32 +1,7 @@
-use super::streaming;
-use codec::Streaming;
+use codec::Decoder;
+use generic;
 
-use futures::{Future, Poll};
-use http::Response;
-use prost::Message;
-use tower_h2::{Body, Data};
-
-#[derive(Debug)]
-pub struct ResponseFuture<T, U> {
-    inner: streaming::ResponseFuture<T, U>,
-}
-
-impl<T, U> ResponseFuture<T, U> {
-    /// Create a new client-streaming response future.
-    pub(crate) fn new(inner: streaming::ResponseFuture<T, U>) -> Self {
-        ResponseFuture { inner }
-    }
-}
-
-impl<T, U, B> Future for ResponseFuture<T, U>
-where T: Message + Default,
-      U: Future<Item = Response<B>>,
-      B: Body<Data = Data>,
-{
-    type Item = ::Response<Streaming<T, B>>;
-    type Error = ::Error<U::Error>;
-
-    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        self.inner.poll()
-    }
-}
+/// Response future of a server streaming call returning protobuf
+/// messages `T`.
+pub type ResponseFuture<T, U> =
+    generic::client::server_streaming::ResponseFuture<Decoder<T>, U>;
@@ -92,7 +92,7 @@ where T: Message,
         }
 
         item.encode(buf)
-            .map_err(|_| unreachable!("Message only errors if not enough space"))
+            .map_err(::Error::Encode)
     }
 }
 
@@ -11,6 +11,8 @@ pub mod server {
         };
         pub use ::server::{
             Grpc,
+            Interceptor,
+            NamedService,
             unary,
             client_streaming,
             server_streaming,
@@ -35,7 +37,7 @@ pub mod server {
 
     /// Re-exported types from the `http` crate.
     pub mod http {
-        pub use ::http::{Request, Response, HeaderMap};
+        pub use ::http::{Request, Response, HeaderMap, header};
     }
 
     /// Re-exported types from the `h2` crate.
@@ -60,7 +62,7 @@ pub mod client {
     pub mod grpc {
         pub use ::client::{
             Grpc,
-            Encodable,
+            Interceptor,
             unary,
             client_streaming,
             server_streaming,
@@ -75,10 +77,10 @@ pub mod client {
 
     /// Re-export types from the `future` crate.
     pub mod futures {
-        pub use ::futures::{Future, Poll};
+        pub use ::futures::{Future, Stream, Poll};
     }
 
     pub mod tower_h2 {
-        pub use ::tower_h2::HttpService;
+        pub use ::tower_h2::{HttpService, BoxBody};
     }
 }
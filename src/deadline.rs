@@ -0,0 +1,234 @@
+//! gRPC deadlines.
+//!
+//! A client sends the time remaining before its deadline in the
+//! `grpc-timeout` header, as at most 8 digits followed by a unit: `H`ours,
+//! `M`inutes, `S`econds, `m`illiseconds, `u` microseconds or `n`anoseconds.
+
+use futures::{Async, Future};
+use http::HeaderMap;
+use http::header::HeaderValue;
+use tokio_timer::Delay;
+
+use std::time::{Duration, Instant};
+
+pub(crate) const TIMEOUT_HEADER: &str = "grpc-timeout";
+
+/// The largest value allowed in `grpc-timeout`.
+const MAX_TIMEOUT_VALUE: u64 = 99_999_999;
+
+/// The deadline of a request, stored in its extensions.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Deadline(pub(crate) Instant);
+
+/// Fires once a deadline has passed.
+#[derive(Debug, Default)]
+pub(crate) struct Timer {
+    delay: Option<Delay>,
+}
+
+// ===== impl Timer =====
+
+impl Timer {
+    pub(crate) fn new(deadline: Option<Instant>) -> Self {
+        Timer {
+            delay: deadline.map(Delay::new),
+        }
+    }
+
+    /// Returns true, once, when the deadline has passed.
+    ///
+    /// If the deadline has not passed, the current task is notified when it
+    /// does.
+    pub(crate) fn poll_expired(&mut self) -> bool {
+        let expired = match self.delay {
+            Some(ref mut delay) => match delay.poll() {
+                Ok(Async::Ready(())) => true,
+                Ok(Async::NotReady) => return false,
+                Err(e) => {
+                    warn!("deadline timer failed; err={}", e);
+                    false
+                }
+            },
+            None => return false,
+        };
+
+        // The deadline only fires once, and a failed timer is not retried.
+        self.delay = None;
+        expired
+    }
+}
+
+// ===== utility fns =====
+
+/// Get the deadline from a request's `grpc-timeout` header.
+///
+/// An invalid timeout is ignored.
+pub(crate) fn from_headers(headers: &HeaderMap) -> Option<Instant> {
+    let value = headers.get(TIMEOUT_HEADER)?;
+
+    match decode_timeout(value) {
+        Some(timeout) => Some(Instant::now() + timeout),
+        None => {
+            debug!("ignoring invalid grpc-timeout; value={:?}", value);
+            None
+        }
+    }
+}
+
+/// Encode the time remaining before `deadline` as a `grpc-timeout` value.
+pub(crate) fn encode_deadline(deadline: Instant) -> HeaderValue {
+    let now = Instant::now();
+
+    if deadline > now {
+        encode_timeout(deadline - now)
+    } else {
+        encode_timeout(Duration::from_secs(0))
+    }
+}
+
+/// Encode `timeout` in the most precise unit that fits in 8 digits,
+/// rounding up.
+pub(crate) fn encode_timeout(timeout: Duration) -> HeaderValue {
+    const UNITS: &[(u64, char)] = &[
+        (1, 'n'),
+        (1_000, 'u'),
+        (1_000_000, 'm'),
+        (1_000_000_000, 'S'),
+        (60 * 1_000_000_000, 'M'),
+        (60 * 60 * 1_000_000_000, 'H'),
+    ];
+
+    let nanos = timeout.as_secs()
+        .saturating_mul(1_000_000_000)
+        .saturating_add(timeout.subsec_nanos() as u64);
+
+    for &(per_unit, unit) in UNITS {
+        let value = nanos / per_unit + if nanos % per_unit == 0 { 0 } else { 1 };
+
+        if value <= MAX_TIMEOUT_VALUE {
+            return HeaderValue::from_str(&format!("{}{}", value, unit))
+                .expect("grpc-timeout is a valid header value");
+        }
+    }
+
+    HeaderValue::from_static("99999999H")
+}
+
+/// Decode a `grpc-timeout` value.
+pub(crate) fn decode_timeout(value: &HeaderValue) -> Option<Duration> {
+    let value = value.to_str().ok()?;
+
+    if value.len() < 2 || value.len() > 9 {
+        return None;
+    }
+
+    let (digits, unit) = value.split_at(value.len() - 1);
+
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let n: u64 = digits.parse().ok()?;
+
+    let timeout = match unit {
+        "H" => Duration::from_secs(n * 60 * 60),
+        "M" => Duration::from_secs(n * 60),
+        "S" => Duration::from_secs(n),
+        "m" => Duration::from_millis(n),
+        "u" => Duration::from_micros(n),
+        "n" => Duration::from_nanos(n),
+        _ => return None,
+    };
+
+    Some(timeout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_picks_most_precise_unit() {
+        let cases = [
+            (Duration::from_nanos(0), "0n"),
+            (Duration::from_nanos(1), "1n"),
+            (Duration::from_nanos(99_999_999), "99999999n"),
+            (Duration::from_nanos(100_000_000), "100000u"),
+            (Duration::from_secs(1), "1000000u"),
+            (Duration::from_secs(100), "100000m"),
+            (Duration::from_secs(24 * 60 * 60), "86400000m"),
+            (Duration::from_secs(100_000), "100000S"),
+            (Duration::from_secs(100_000_000), "1666667M"),
+            (Duration::from_secs(10_000_000_000), "2777778H"),
+            (Duration::from_secs(::std::u64::MAX), "5124096H"),
+        ];
+
+        for &(timeout, expected) in &cases {
+            assert_eq!(encode_timeout(timeout), expected, "timeout={:?}", timeout);
+        }
+    }
+
+    #[test]
+    fn encode_rounds_up() {
+        assert_eq!(encode_timeout(Duration::from_nanos(100_000_001)), "100001u");
+        assert_eq!(encode_timeout(Duration::new(100, 1)), "100001m");
+    }
+
+    #[test]
+    fn decode_units() {
+        let cases = [
+            ("1H", Duration::from_secs(60 * 60)),
+            ("2M", Duration::from_secs(2 * 60)),
+            ("3S", Duration::from_secs(3)),
+            ("4m", Duration::from_millis(4)),
+            ("5u", Duration::from_micros(5)),
+            ("6n", Duration::from_nanos(6)),
+            ("0S", Duration::from_secs(0)),
+            ("99999999S", Duration::from_secs(99_999_999)),
+            ("00000001m", Duration::from_millis(1)),
+        ];
+
+        for &(value, expected) in &cases {
+            let decoded = decode_timeout(&HeaderValue::from_static(value));
+            assert_eq!(decoded, Some(expected), "value={:?}", value);
+        }
+    }
+
+    #[test]
+    fn decode_rejects_invalid_values() {
+        let cases = [
+            "",
+            "S",
+            "1",
+            "123456789S",
+            "1s",
+            "1h",
+            "1x",
+            "-1S",
+            "+1S",
+            "1.5S",
+            " 1S",
+            "1S ",
+        ];
+
+        for &value in &cases {
+            let decoded = decode_timeout(&HeaderValue::from_static(value));
+            assert_eq!(decoded, None, "value={:?}", value);
+        }
+    }
+
+    #[test]
+    fn encoded_timeouts_decode_to_at_least_the_timeout() {
+        let timeouts = [
+            Duration::from_nanos(1),
+            Duration::from_millis(1500),
+            Duration::new(100, 1),
+            Duration::from_secs(24 * 60 * 60),
+        ];
+
+        for &timeout in &timeouts {
+            let decoded = decode_timeout(&encode_timeout(timeout)).expect("decode");
+            assert!(decoded >= timeout, "timeout={:?}; decoded={:?}", timeout, decoded);
+        }
+    }
+}
@@ -1,12 +1,19 @@
-use prost::DecodeError;
+use Status;
+
+use prost::{DecodeError, EncodeError};
 use http::HeaderMap;
 use h2;
 
+use std::error;
+use std::fmt;
+
 #[derive(Debug)]
 pub enum Error<T = ()> {
     Grpc(::Status, HeaderMap),
     Protocol(ProtocolError),
     Decode(DecodeError),
+    Encode(EncodeError),
+    Http2(h2::Error),
     Inner(T),
 }
 
@@ -14,9 +21,35 @@ pub enum Error<T = ()> {
 pub enum ProtocolError {
     MissingTrailers,
     MissingMessage,
+    ExtraMessage,
     UnexpectedEof,
     Internal,
     UnsupportedCompressionFlag(u8),
+    InvalidText,
+    InvalidTrailers,
+}
+
+// ===== impl Error =====
+
+impl<T> Error<T> {
+    /// Returns the gRPC status that a caller should see for this error.
+    ///
+    /// Errors produced by the inner service carry no status of their own,
+    /// so they are reported as `UNKNOWN`.
+    pub fn status(&self) -> Status {
+        match *self {
+            Error::Grpc(ref status, _) => status.clone(),
+            Error::Protocol(ref err) => Status::INTERNAL.with_message(err.to_string()),
+            Error::Decode(ref err) => {
+                Status::INTERNAL.with_message(format!("failed to decode message: {}", err))
+            }
+            Error::Encode(ref err) => {
+                Status::INTERNAL.with_message(format!("failed to encode message: {}", err))
+            }
+            Error::Http2(ref err) => Status::from_h2_error(err),
+            Error::Inner(_) => Status::UNKNOWN,
+        }
+    }
 }
 
 impl<T> From<T> for Error<T> {
@@ -26,15 +59,139 @@ impl<T> From<T> for Error<T> {
 }
 
 impl From<Error<()>> for h2::Error {
-    fn from(_err: Error<()>) -> Self {
-        // TODO: implement
-        h2::Reason::INTERNAL_ERROR.into()
+    fn from(err: Error<()>) -> Self {
+        match err {
+            Error::Http2(err) => err,
+            err => err.status().into(),
+        }
     }
 }
 
 impl From<h2::Error> for Error<()> {
-    fn from(_: h2::Error) -> Self {
-        // TODO: implement
-        Error::Inner(())
+    fn from(err: h2::Error) -> Self {
+        Error::Http2(err)
+    }
+}
+
+impl<T> fmt::Display for Error<T>
+where T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Grpc(ref status, _) => write!(f, "grpc-status: {}", status),
+            Error::Protocol(ref err) => write!(f, "protocol error: {}", err),
+            Error::Decode(ref err) => write!(f, "failed to decode message: {}", err),
+            Error::Encode(ref err) => write!(f, "failed to encode message: {}", err),
+            Error::Http2(ref err) => write!(f, "HTTP/2 error: {}", err),
+            Error::Inner(ref inner) => write!(f, "inner service error: {:?}", inner),
+        }
+    }
+}
+
+impl<T> error::Error for Error<T>
+where T: fmt::Debug,
+{
+    fn description(&self) -> &str {
+        match *self {
+            Error::Grpc(..) => "gRPC status error",
+            Error::Protocol(..) => "gRPC protocol error",
+            Error::Decode(..) => "failed to decode message",
+            Error::Encode(..) => "failed to encode message",
+            Error::Http2(..) => "HTTP/2 error",
+            Error::Inner(..) => "inner service error",
+        }
+    }
+
+    // The inner error is not required to implement `Error`, so it cannot be
+    // returned here.
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Grpc(ref status, _) => Some(status),
+            Error::Protocol(ref err) => Some(err),
+            Error::Decode(ref err) => Some(err),
+            Error::Encode(ref err) => Some(err),
+            Error::Http2(ref err) => Some(err),
+            Error::Inner(_) => None,
+        }
+    }
+}
+
+// ===== impl ProtocolError =====
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ProtocolError::MissingTrailers => f.write_str("missing grpc-status trailer"),
+            ProtocolError::MissingMessage => f.write_str("stream ended without a message"),
+            ProtocolError::ExtraMessage => f.write_str("stream had more than one message"),
+            ProtocolError::UnexpectedEof => f.write_str("stream ended in the middle of a message"),
+            ProtocolError::Internal => f.write_str("internal error"),
+            ProtocolError::UnsupportedCompressionFlag(flag) => {
+                write!(f, "unsupported compression flag: {}", flag)
+            }
+            ProtocolError::InvalidText => f.write_str("invalid base64 in gRPC-Web text body"),
+            ProtocolError::InvalidTrailers => f.write_str("invalid gRPC-Web trailers frame"),
+        }
+    }
+}
+
+impl error::Error for ProtocolError {
+    fn description(&self) -> &str {
+        "gRPC protocol error"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Code;
+    use error_details::Any;
+
+    use bytes::Bytes;
+    use prost::Message;
+
+    use std::error::Error as StdError;
+    use std::io;
+
+    fn decode_error() -> DecodeError {
+        // A length-delimited field without its length.
+        Any::decode(Bytes::from_static(b"\x0a")).unwrap_err()
+    }
+
+    fn encode_error() -> EncodeError {
+        let any = Any { type_url: "type".to_string(), value: Vec::new() };
+        let mut buf = [0u8; 1];
+        any.encode(&mut io::Cursor::new(&mut buf[..])).unwrap_err()
+    }
+
+    #[test]
+    fn display_source_and_status() {
+        let cases: Vec<(Error<&'static str>, &str, bool, Code)> = vec![
+            (Error::Grpc(Status::NOT_FOUND.with_message("gone"), HeaderMap::new()),
+             "grpc-status: NOT_FOUND: gone", true, Code::NOT_FOUND),
+            (Error::Protocol(ProtocolError::MissingTrailers),
+             "protocol error: missing grpc-status trailer", true, Code::INTERNAL),
+            (Error::Decode(decode_error()), "failed to decode message: ", true, Code::INTERNAL),
+            (Error::Encode(encode_error()), "failed to encode message: ", true, Code::INTERNAL),
+            (Error::Http2(h2::Reason::REFUSED_STREAM.into()), "HTTP/2 error: ", true, Code::UNAVAILABLE),
+            (Error::Inner("boom"), "inner service error: \"boom\"", false, Code::UNKNOWN),
+        ];
+
+        for (err, display, source, code) in cases {
+            assert!(err.to_string().starts_with(display), "{}", err);
+            assert_eq!(err.source().is_some(), source, "{}", err);
+            assert_eq!(err.status().code(), code, "{}", err);
+        }
+    }
+
+    #[test]
+    fn h2_errors_keep_their_reason() {
+        let err: Error = h2::Error::from(h2::Reason::REFUSED_STREAM).into();
+        assert_eq!(err.status().code(), Code::UNAVAILABLE);
+        assert_eq!(h2::Error::from(err).reason(), Some(h2::Reason::REFUSED_STREAM));
+
+        // Other errors reset the stream with the reason for their status.
+        let err: Error = Error::Protocol(ProtocolError::Internal);
+        assert_eq!(h2::Error::from(err).reason(), Some(h2::Reason::INTERNAL_ERROR));
     }
 }
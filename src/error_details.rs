@@ -0,0 +1,309 @@
+//! The `google.rpc.Status` rich error model.
+//!
+//! Other gRPC implementations carry structured error details by sending a
+//! serialized `google.rpc.Status` in the `grpc-status-details-bin` trailer.
+//! The `code` and `message` of that message mirror `grpc-status` and
+//! `grpc-message`, and `details` holds a list of `google.protobuf.Any`
+//! messages, usually one of the standard types in `google/rpc/error_details.proto`.
+
+use Status;
+
+use prost::{DecodeError, Message};
+
+use std::collections::HashMap;
+use std::time;
+
+const TYPE_URL_PREFIX: &str = "type.googleapis.com/";
+
+/// A typed error detail.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorDetail {
+    BadRequest(BadRequest),
+    RetryInfo(RetryInfo),
+    ErrorInfo(ErrorInfo),
+    QuotaFailure(QuotaFailure),
+    DebugInfo(DebugInfo),
+    ResourceInfo(ResourceInfo),
+    LocalizedMessage(LocalizedMessage),
+    /// A detail of a type not known to this crate.
+    Unknown(Any),
+}
+
+/// `google.protobuf.Any`
+#[derive(Clone, PartialEq, Message)]
+pub struct Any {
+    #[prost(string, tag="1")]
+    pub type_url: String,
+    #[prost(bytes, tag="2")]
+    pub value: Vec<u8>,
+}
+
+/// `google.protobuf.Duration`
+#[derive(Clone, PartialEq, Message)]
+pub struct Duration {
+    #[prost(int64, tag="1")]
+    pub seconds: i64,
+    #[prost(int32, tag="2")]
+    pub nanos: i32,
+}
+
+/// `google.rpc.Status`
+#[derive(Clone, PartialEq, Message)]
+struct RpcStatus {
+    #[prost(int32, tag="1")]
+    code: i32,
+    #[prost(string, tag="2")]
+    message: String,
+    #[prost(message, repeated, tag="3")]
+    details: Vec<Any>,
+}
+
+/// Describes violations in a client request.
+#[derive(Clone, PartialEq, Message)]
+pub struct BadRequest {
+    #[prost(message, repeated, tag="1")]
+    pub field_violations: Vec<bad_request::FieldViolation>,
+}
+
+pub mod bad_request {
+    /// A single bad request field.
+    #[derive(Clone, PartialEq, Message)]
+    pub struct FieldViolation {
+        #[prost(string, tag="1")]
+        pub field: String,
+        #[prost(string, tag="2")]
+        pub description: String,
+    }
+}
+
+/// Describes when the client may retry a failed request.
+#[derive(Clone, PartialEq, Message)]
+pub struct RetryInfo {
+    #[prost(message, optional, tag="1")]
+    pub retry_delay: Option<Duration>,
+}
+
+/// Describes the cause of the error with structured details.
+#[derive(Clone, PartialEq, Message)]
+pub struct ErrorInfo {
+    #[prost(string, tag="1")]
+    pub reason: String,
+    #[prost(string, tag="2")]
+    pub domain: String,
+    #[prost(map="string, string", tag="3")]
+    pub metadata: HashMap<String, String>,
+}
+
+/// Describes how a quota check failed.
+#[derive(Clone, PartialEq, Message)]
+pub struct QuotaFailure {
+    #[prost(message, repeated, tag="1")]
+    pub violations: Vec<quota_failure::Violation>,
+}
+
+pub mod quota_failure {
+    /// A single quota violation.
+    #[derive(Clone, PartialEq, Message)]
+    pub struct Violation {
+        #[prost(string, tag="1")]
+        pub subject: String,
+        #[prost(string, tag="2")]
+        pub description: String,
+    }
+}
+
+/// Describes additional debugging info.
+#[derive(Clone, PartialEq, Message)]
+pub struct DebugInfo {
+    #[prost(string, repeated, tag="1")]
+    pub stack_entries: Vec<String>,
+    #[prost(string, tag="2")]
+    pub detail: String,
+}
+
+/// Describes the resource that is being accessed.
+#[derive(Clone, PartialEq, Message)]
+pub struct ResourceInfo {
+    #[prost(string, tag="1")]
+    pub resource_type: String,
+    #[prost(string, tag="2")]
+    pub resource_name: String,
+    #[prost(string, tag="3")]
+    pub owner: String,
+    #[prost(string, tag="4")]
+    pub description: String,
+}
+
+/// Provides a localized error message that is safe to return to the user.
+#[derive(Clone, PartialEq, Message)]
+pub struct LocalizedMessage {
+    #[prost(string, tag="1")]
+    pub locale: String,
+    #[prost(string, tag="2")]
+    pub message: String,
+}
+
+// ===== impl Status =====
+
+impl Status {
+    /// Returns a copy of this status with the given error details encoded
+    /// as a `google.rpc.Status` in `grpc-status-details-bin`.
+    ///
+    /// The encoded status copies the current code and message, so any
+    /// message should be set before calling this.
+    pub fn with_error_details(self, details: Vec<ErrorDetail>) -> Status {
+        let status = RpcStatus {
+            code: self.code().to_i32(),
+            message: self.message().to_string(),
+            details: details.into_iter()
+                .map(ErrorDetail::into_any)
+                .collect(),
+        };
+
+        let details = encode(&status);
+        self.with_details(details.into())
+    }
+
+    /// Decode the error details carried in `grpc-status-details-bin`.
+    ///
+    /// Returns an empty list if the status has no details.
+    pub fn error_details(&self) -> Result<Vec<ErrorDetail>, DecodeError> {
+        if self.details().is_empty() {
+            return Ok(vec![]);
+        }
+
+        let status = RpcStatus::decode(self.details())?;
+
+        status.details.into_iter()
+            .map(ErrorDetail::from_any)
+            .collect()
+    }
+}
+
+// ===== impl ErrorDetail =====
+
+impl ErrorDetail {
+    /// Encode the detail as a `google.protobuf.Any`.
+    pub fn into_any(self) -> Any {
+        use self::ErrorDetail::*;
+
+        let (name, value) = match self {
+            BadRequest(ref v) => ("google.rpc.BadRequest", encode(v)),
+            RetryInfo(ref v) => ("google.rpc.RetryInfo", encode(v)),
+            ErrorInfo(ref v) => ("google.rpc.ErrorInfo", encode(v)),
+            QuotaFailure(ref v) => ("google.rpc.QuotaFailure", encode(v)),
+            DebugInfo(ref v) => ("google.rpc.DebugInfo", encode(v)),
+            ResourceInfo(ref v) => ("google.rpc.ResourceInfo", encode(v)),
+            LocalizedMessage(ref v) => ("google.rpc.LocalizedMessage", encode(v)),
+            Unknown(any) => return any,
+        };
+
+        Any {
+            type_url: format!("{}{}", TYPE_URL_PREFIX, name),
+            value,
+        }
+    }
+
+    /// Decode a detail from a `google.protobuf.Any`.
+    ///
+    /// Details of an unrecognized type are returned as `ErrorDetail::Unknown`.
+    pub fn from_any(any: Any) -> Result<ErrorDetail, DecodeError> {
+        use self::ErrorDetail::*;
+
+        // Only the last path segment of the type URL names the message.
+        let detail = match any.type_url.rsplit('/').next().unwrap_or("") {
+            "google.rpc.BadRequest" => BadRequest(Message::decode(&any.value[..])?),
+            "google.rpc.RetryInfo" => RetryInfo(Message::decode(&any.value[..])?),
+            "google.rpc.ErrorInfo" => ErrorInfo(Message::decode(&any.value[..])?),
+            "google.rpc.QuotaFailure" => QuotaFailure(Message::decode(&any.value[..])?),
+            "google.rpc.DebugInfo" => DebugInfo(Message::decode(&any.value[..])?),
+            "google.rpc.ResourceInfo" => ResourceInfo(Message::decode(&any.value[..])?),
+            "google.rpc.LocalizedMessage" => LocalizedMessage(Message::decode(&any.value[..])?),
+            _ => Unknown(any),
+        };
+
+        Ok(detail)
+    }
+}
+
+// ===== impl Duration =====
+
+impl From<time::Duration> for Duration {
+    fn from(duration: time::Duration) -> Self {
+        Duration {
+            seconds: duration.as_secs() as i64,
+            nanos: duration.subsec_nanos() as i32,
+        }
+    }
+}
+
+impl From<Duration> for time::Duration {
+    /// Negative durations, which `std` cannot represent, become zero.
+    fn from(duration: Duration) -> Self {
+        if duration.seconds < 0 || duration.nanos < 0 {
+            return time::Duration::from_secs(0);
+        }
+
+        time::Duration::new(duration.seconds as u64, duration.nanos as u32)
+    }
+}
+
+// ===== utility fns =====
+
+fn encode<T: Message>(message: &T) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(message.encoded_len());
+    message.encode(&mut buf)
+        .expect("Vec<u8> grows to fit the encoded message");
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_details_round_trip() {
+        let details = vec![
+            ErrorDetail::RetryInfo(RetryInfo {
+                retry_delay: Some(time::Duration::from_millis(1500).into()),
+            }),
+            ErrorDetail::BadRequest(BadRequest {
+                field_violations: vec![bad_request::FieldViolation {
+                    field: "name".to_string(),
+                    description: "must not be empty".to_string(),
+                }],
+            }),
+            ErrorDetail::Unknown(Any {
+                type_url: "type.googleapis.com/example.Custom".to_string(),
+                value: vec![1, 2, 3],
+            }),
+        ];
+
+        let status = Status::INVALID_ARGUMENT
+            .with_message("bad request")
+            .with_error_details(details.clone());
+
+        let rpc_status = RpcStatus::decode(status.details()).expect("decode");
+        assert_eq!(rpc_status.code, 3);
+        assert_eq!(rpc_status.message, "bad request");
+
+        assert_eq!(status.error_details().expect("error_details"), details);
+    }
+
+    #[test]
+    fn no_error_details() {
+        assert_eq!(Status::INTERNAL.error_details().expect("error_details"), vec![]);
+    }
+
+    #[test]
+    fn duration_round_trip() {
+        let delay = time::Duration::new(2, 500_000_000);
+        let proto = Duration::from(delay);
+
+        assert_eq!(proto, Duration { seconds: 2, nanos: 500_000_000 });
+        assert_eq!(time::Duration::from(proto), delay);
+
+        let negative = Duration { seconds: -1, nanos: 0 };
+        assert_eq!(time::Duration::from(negative), time::Duration::from_secs(0));
+    }
+}
@@ -0,0 +1,176 @@
+use super::streaming;
+use generic::{Decoder, Streaming};
+
+use bytes::Bytes;
+use futures::{Future, Stream, Poll};
+use http::{response, Response};
+use tower_h2::Body;
+use error::ProtocolError;
+
+#[derive(Debug)]
+pub struct ResponseFuture<T, U, B>
+where T: Decoder,
+{
+    state: State<T, U, B>,
+}
+
+#[derive(Debug)]
+enum State<T, U, B>
+where T: Decoder,
+{
+    WaitResponse(streaming::ResponseFuture<T, U>),
+    /// Reads the stream to its end, so a `grpc-status` in the trailers is
+    /// not mistaken for success and the call is completed.
+    WaitMessage {
+        head: Option<response::Parts>,
+        message: Option<T::Item>,
+        stream: Streaming<T, B>,
+    },
+}
+
+impl<T, U, B> ResponseFuture<T, U, B>
+where T: Decoder,
+{
+    /// Create a new client-streaming response future.
+    pub(crate) fn new(inner: streaming::ResponseFuture<T, U>) -> Self {
+        let state = State::WaitResponse(inner);
+        ResponseFuture { state }
+    }
+}
+
+impl<T, U, B> Future for ResponseFuture<T, U, B>
+where T: Decoder,
+      U: Future<Item = Response<B>>,
+      B: Body,
+      B::Data: Into<Bytes>,
+{
+    type Item = ::Response<T::Item>;
+    type Error = ::Error<U::Error>;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        use self::State::*;
+
+        loop {
+            let response = match self.state {
+                WaitResponse(ref mut inner) => {
+                    try_ready!(inner.poll())
+                }
+                WaitMessage { ref mut head, ref mut message, ref mut stream } => {
+                    let res = stream.poll()
+                        .map_err(|e| match e {
+                            ::Error::Protocol(p) => ::Error::Protocol(p),
+                            ::Error::Inner(()) => ::Error::Protocol(ProtocolError::Internal),
+                            ::Error::Decode(e) => ::Error::Decode(e),
+                            ::Error::Encode(e) => ::Error::Encode(e),
+                            ::Error::Http2(e) => ::Error::Http2(e),
+                            ::Error::Grpc(s, h) => ::Error::Grpc(s, h),
+                        });
+
+                    match try_ready!(res) {
+                        Some(_) if message.is_some() => {
+                            return Err(::Error::Protocol(ProtocolError::ExtraMessage));
+                        }
+                        Some(received) => {
+                            *message = Some(received);
+                            continue;
+                        }
+                        None => {}
+                    }
+
+                    let message = match message.take() {
+                        Some(message) => message,
+                        None => return Err(::Error::Protocol(ProtocolError::MissingMessage)),
+                    };
+
+                    let head = head.take().unwrap();
+                    let response = Response::from_parts(head, message);
+
+                    return Ok(::Response::from_http(response).into());
+                }
+            };
+
+            let (head, body) = response
+                .into_http()
+                .into_parts();
+
+            self.state = WaitMessage {
+                head: Some(head),
+                message: None,
+                stream: body,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {CancelHandle, Code};
+    use generic::BytesDecoder;
+    use generic::compression::Compression;
+    use test_util::{frame, TestBody};
+
+    use futures::future;
+    use http::HeaderMap;
+    use http::header::HeaderValue;
+
+    type Call = ResponseFuture<BytesDecoder, future::FutureResult<Response<TestBody>, ()>, TestBody>;
+
+    /// A call answered with `messages`, then a `grpc-status` of `code`.
+    fn call(messages: &[&'static [u8]], code: &'static str, cancel: &CancelHandle) -> Call {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", HeaderValue::from_static(code));
+
+        let body = TestBody::new(messages.iter().map(|message| frame(message)))
+            .with_trailers(trailers);
+
+        let response = streaming::ResponseFuture::new(
+            future::ok(Response::new(body)),
+            BytesDecoder,
+            Compression::none(),
+            None,
+            None,
+            None,
+            Some(cancel.clone()));
+
+        ResponseFuture::new(response)
+    }
+
+    #[test]
+    fn message_is_returned_at_the_end_of_the_stream() {
+        let cancel = CancelHandle::new();
+
+        let response = call(&[b"hello"], "0", &cancel).wait().expect("response");
+        assert_eq!(response.into_inner(), &b"hello"[..]);
+
+        // Reaching the end of the stream completed the call.
+        assert_eq!(cancel.waiting_tasks(), 0);
+    }
+
+    #[test]
+    fn error_status_after_the_message_fails_the_call() {
+        let cancel = CancelHandle::new();
+
+        match call(&[b"hello"], "13", &cancel).wait() {
+            Err(::Error::Grpc(status, _)) => assert_eq!(status.code(), Code::INTERNAL),
+            res => panic!("unexpected result: {:?}", res),
+        }
+
+        assert_eq!(cancel.waiting_tasks(), 0);
+    }
+
+    #[test]
+    fn missing_or_extra_messages_are_errors() {
+        let cancel = CancelHandle::new();
+
+        match call(&[], "0", &cancel).wait() {
+            Err(::Error::Protocol(ProtocolError::MissingMessage)) => {}
+            res => panic!("unexpected result: {:?}", res),
+        }
+
+        match call(&[b"hello", b"again"], "0", &cancel).wait() {
+            Err(::Error::Protocol(ProtocolError::ExtraMessage)) => {}
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+}
@@ -0,0 +1,577 @@
+pub mod unary;
+pub mod client_streaming;
+pub mod server_streaming;
+pub mod streaming;
+
+use {MetadataMap, Request, Status};
+use deadline;
+use generic::{Codec, Encode, EncodeOptions, DEFAULT_MAX_DECODING_MESSAGE_SIZE};
+use generic::compression::{Compression, ENCODING_HEADER, ACCEPT_ENCODING_HEADER};
+use web::Mode;
+
+use futures::{stream, Stream};
+use http::{uri, Uri};
+use tower_h2::{HttpService, BoxBody};
+
+use std::fmt;
+use std::sync::Arc;
+
+/// gRPC client configuration, generic over the codec.
+///
+/// The HTTP/2.0 service is passed to each call.
+#[derive(Clone)]
+pub struct Grpc<T> {
+    /// Encodes requests and decodes responses.
+    codec: T,
+
+    /// Settings shared by every call.
+    config: Config,
+}
+
+/// Client settings that do not depend on the codec.
+///
+/// Shared with the protobuf client, which picks its codec per call.
+#[derive(Clone)]
+pub(crate) struct Config {
+    /// Compression algorithms used for requests and responses.
+    compression: Compression,
+
+    /// The largest response message accepted, if limited.
+    max_decoding_message_size: Option<usize>,
+
+    /// The largest request message sent, if limited.
+    max_encoding_message_size: Option<usize>,
+
+    /// Inspects outgoing requests, if set.
+    interceptor: Option<Arc<dyn Interceptor>>,
+
+    /// Speaks gRPC-Web instead of gRPC, if set.
+    web: Option<Mode>,
+}
+
+/// Intercepts outgoing requests before they are sent.
+///
+/// Implemented for closures of the same signature as `intercept`.
+pub trait Interceptor: Send + Sync {
+    /// Inspect or modify the metadata of a request.
+    ///
+    /// Returning an error fails the call with that status without sending it.
+    fn intercept(&self, metadata: &mut MetadataMap) -> Result<(), Status>;
+}
+
+// ===== impl Grpc =====
+
+impl<T> Grpc<T> {
+    /// Create a new `Grpc` instance encoding messages with `codec`.
+    pub fn new(codec: T) -> Self {
+        Grpc {
+            codec,
+            config: Config::new(),
+        }
+    }
+
+    /// Pass every request through `interceptor`, replacing any previous one.
+    pub fn set_interceptor<I>(&mut self, interceptor: I) -> &mut Self
+    where I: Interceptor + 'static,
+    {
+        self.config.set_interceptor(interceptor);
+        self
+    }
+
+    /// Set the compression algorithms used for requests and responses.
+    pub fn set_compression(&mut self, compression: Compression) -> &mut Self {
+        self.config.set_compression(compression);
+        self
+    }
+
+    /// Limit the size of response messages, in bytes.
+    ///
+    /// Larger responses fail with `RESOURCE_EXHAUSTED` before being
+    /// buffered. Defaults to 4 MiB; `None` removes the limit.
+    pub fn set_max_decoding_message_size(&mut self, limit: Option<usize>) -> &mut Self {
+        self.config.set_max_decoding_message_size(limit);
+        self
+    }
+
+    /// Limit the size of request messages, in bytes.
+    ///
+    /// Larger requests fail with `RESOURCE_EXHAUSTED`. Unlimited by default.
+    pub fn set_max_encoding_message_size(&mut self, limit: Option<usize>) -> &mut Self {
+        self.config.set_max_encoding_message_size(limit);
+        self
+    }
+
+    /// Speak gRPC-Web in the given mode, or gRPC with `None`.
+    ///
+    /// For servers that are only reachable through a gRPC-Web proxy, or
+    /// over connections that cannot carry trailers.
+    pub fn set_web_mode(&mut self, mode: Option<Mode>) -> &mut Self {
+        self.config.set_web_mode(mode);
+        self
+    }
+}
+
+impl<T> Grpc<T>
+where T: Codec,
+{
+    pub fn unary<S>(&mut self,
+                    inner: &mut S,
+                    request: Request<T::Encode>,
+                    path: uri::PathAndQuery)
+        -> unary::ResponseFuture<T::Decoder, S::Future, S::ResponseBody>
+    where S: HttpService<RequestBody = BoxBody>,
+          T::Encode: Send + 'static,
+          T::Encoder: Send + 'static,
+    {
+        self.config.unary(&mut self.codec, inner, request, path)
+    }
+
+    pub fn client_streaming<S, B>(&mut self,
+                                  inner: &mut S,
+                                  request: Request<B>,
+                                  path: uri::PathAndQuery)
+        -> client_streaming::ResponseFuture<T::Decoder, S::Future, S::ResponseBody>
+    where S: HttpService<RequestBody = BoxBody>,
+          B: Stream<Item = T::Encode, Error = ::Error> + Send + 'static,
+          T::Encoder: Send + 'static,
+    {
+        self.config.client_streaming(&mut self.codec, inner, request, path)
+    }
+
+    pub fn server_streaming<S>(&mut self,
+                               inner: &mut S,
+                               request: Request<T::Encode>,
+                               path: uri::PathAndQuery)
+        -> server_streaming::ResponseFuture<T::Decoder, S::Future>
+    where S: HttpService<RequestBody = BoxBody>,
+          T::Encode: Send + 'static,
+          T::Encoder: Send + 'static,
+    {
+        self.config.server_streaming(&mut self.codec, inner, request, path)
+    }
+
+    /// Initiate a full streaming gRPC request
+    ///
+    /// # Generics
+    ///
+    /// **S**: The HTTP/2.0 service sending the request.
+    /// **B**: The request stream of gRPC message values.
+    pub fn streaming<S, B>(&mut self,
+                           inner: &mut S,
+                           request: Request<B>,
+                           path: uri::PathAndQuery)
+        -> streaming::ResponseFuture<T::Decoder, S::Future>
+    where S: HttpService<RequestBody = BoxBody>,
+          B: Stream<Item = T::Encode, Error = ::Error> + Send + 'static,
+          T::Encoder: Send + 'static,
+    {
+        self.config.streaming(&mut self.codec, inner, request, path)
+    }
+}
+
+impl<T> fmt::Debug for Grpc<T>
+where T: fmt::Debug,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Grpc")
+            .field("codec", &self.codec)
+            .field("compression", &self.config.compression)
+            .field("max_decoding_message_size", &self.config.max_decoding_message_size)
+            .field("max_encoding_message_size", &self.config.max_encoding_message_size)
+            .field("interceptor", &self.config.interceptor.is_some())
+            .field("web", &self.config.web)
+            .finish()
+    }
+}
+
+// ===== impl Config =====
+
+impl Config {
+    pub(crate) fn new() -> Self {
+        Config {
+            compression: Compression::new(),
+            max_decoding_message_size: Some(DEFAULT_MAX_DECODING_MESSAGE_SIZE),
+            max_encoding_message_size: None,
+            interceptor: None,
+            web: None,
+        }
+    }
+
+    pub(crate) fn set_interceptor<I>(&mut self, interceptor: I)
+    where I: Interceptor + 'static,
+    {
+        self.interceptor = Some(Arc::new(interceptor));
+    }
+
+    pub(crate) fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    pub(crate) fn set_max_decoding_message_size(&mut self, limit: Option<usize>) {
+        self.max_decoding_message_size = limit;
+    }
+
+    pub(crate) fn set_max_encoding_message_size(&mut self, limit: Option<usize>) {
+        self.max_encoding_message_size = limit;
+    }
+
+    pub(crate) fn set_web_mode(&mut self, mode: Option<Mode>) {
+        self.web = mode;
+    }
+
+    pub(crate) fn unary<C, S>(&self,
+                              codec: &mut C,
+                              inner: &mut S,
+                              request: Request<C::Encode>,
+                              path: uri::PathAndQuery)
+        -> unary::ResponseFuture<C::Decoder, S::Future, S::ResponseBody>
+    where C: Codec,
+          S: HttpService<RequestBody = BoxBody>,
+          C::Encode: Send + 'static,
+          C::Encoder: Send + 'static,
+    {
+        let request = request.map(|v| stream::once(Ok(v)));
+        let response = self.client_streaming(codec, inner, request, path);
+
+        unary::ResponseFuture::new(response)
+    }
+
+    pub(crate) fn client_streaming<C, S, B>(&self,
+                                            codec: &mut C,
+                                            inner: &mut S,
+                                            request: Request<B>,
+                                            path: uri::PathAndQuery)
+        -> client_streaming::ResponseFuture<C::Decoder, S::Future, S::ResponseBody>
+    where C: Codec,
+          S: HttpService<RequestBody = BoxBody>,
+          B: Stream<Item = C::Encode, Error = ::Error> + Send + 'static,
+          C::Encoder: Send + 'static,
+    {
+        let response = self.streaming(codec, inner, request, path);
+        client_streaming::ResponseFuture::new(response)
+    }
+
+    pub(crate) fn server_streaming<C, S>(&self,
+                                         codec: &mut C,
+                                         inner: &mut S,
+                                         request: Request<C::Encode>,
+                                         path: uri::PathAndQuery)
+        -> server_streaming::ResponseFuture<C::Decoder, S::Future>
+    where C: Codec,
+          S: HttpService<RequestBody = BoxBody>,
+          C::Encode: Send + 'static,
+          C::Encoder: Send + 'static,
+    {
+        let request = request.map(|v| stream::once(Ok(v)));
+        let response = self.streaming(codec, inner, request, path);
+
+        server_streaming::ResponseFuture::new(response)
+    }
+
+    /// Send a request, encoding its messages with `codec`.
+    pub(crate) fn streaming<C, S, B>(&self,
+                                     codec: &mut C,
+                                     inner: &mut S,
+                                     mut request: Request<B>,
+                                     path: uri::PathAndQuery)
+        -> streaming::ResponseFuture<C::Decoder, S::Future>
+    where C: Codec,
+          S: HttpService<RequestBody = BoxBody>,
+          B: Stream<Item = C::Encode, Error = ::Error> + Send + 'static,
+          C::Encoder: Send + 'static,
+    {
+        use http::header::{self, HeaderValue};
+
+        // Get the gRPC's method URI
+        let uri = match method_uri(path) {
+            Ok(uri) => uri,
+            Err(status) => return streaming::ResponseFuture::error(status),
+        };
+
+        if let Some(ref interceptor) = self.interceptor {
+            if let Err(status) = interceptor.intercept(request.metadata_mut()) {
+                debug!("request rejected by interceptor; status={:?}", status);
+                return streaming::ResponseFuture::error(status);
+            }
+        }
+
+        let mut options = EncodeOptions::default();
+        options.compressor = self.compression.send_compressor();
+        options.message_compression = request.message_compression();
+        options.max_message_size = self.max_encoding_message_size;
+        options.text = self.web == Some(Mode::Text);
+
+        let deadline = request.deadline();
+        let cancel = request.cancel();
+        options.cancel = cancel.clone();
+
+        // Encode the request body
+        let encoder = codec.encoder();
+        let request = request.map(|body| {
+            BoxBody::new(Box::new(Encode::new(encoder, body, options.clone(), false)))
+        });
+
+        // Convert to an HTTP request
+        let mut request = request.into_http(uri);
+
+        // Negotiate compression
+        if let Some(ref compressor) = options.compressor {
+            request.headers_mut().insert(
+                ENCODING_HEADER,
+                HeaderValue::from_static(compressor.name()));
+        }
+
+        if let Some(accept_encoding) = self.compression.accept_encoding() {
+            request.headers_mut().insert(ACCEPT_ENCODING_HEADER, accept_encoding);
+        }
+
+        // Propagate the time remaining before the deadline
+        if let Some(deadline) = deadline {
+            request.headers_mut().insert(
+                deadline::TIMEOUT_HEADER,
+                deadline::encode_deadline(deadline));
+        }
+
+        // Set the content type
+        let content_type = C::CONTENT_TYPE;
+
+        match self.web {
+            Some(mode) => {
+                // gRPC-Web responses carry the trailers in the body.
+                let content_type = HeaderValue::from_str(&mode.content_type(content_type))
+                    .expect("gRPC-Web content type is a valid header value");
+
+                request.headers_mut().insert(header::CONTENT_TYPE, content_type);
+                request.headers_mut()
+                    .insert("x-grpc-web", HeaderValue::from_static("1"));
+            }
+            None => {
+                // Add the gRPC related HTTP headers
+                request.headers_mut()
+                    .insert(header::TE, HeaderValue::from_static("trailers"));
+
+                request.headers_mut().insert(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static(content_type));
+            }
+        }
+
+        // Call the inner HTTP service
+        let response = inner.call(request);
+
+        streaming::ResponseFuture::new(
+            response,
+            codec.decoder(),
+            self.compression.clone(),
+            self.max_decoding_message_size,
+            deadline,
+            self.web,
+            cancel)
+    }
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Config")
+            .field("compression", &self.compression)
+            .field("max_decoding_message_size", &self.max_decoding_message_size)
+            .field("max_encoding_message_size", &self.max_encoding_message_size)
+            .field("interceptor", &self.interceptor.is_some())
+            .field("web", &self.web)
+            .finish()
+    }
+}
+
+// ===== impl Interceptor =====
+
+impl<F> Interceptor for F
+where F: Fn(&mut MetadataMap) -> Result<(), Status> + Send + Sync,
+{
+    fn intercept(&self, metadata: &mut MetadataMap) -> Result<(), Status> {
+        self(metadata)
+    }
+}
+
+// ===== utility fns =====
+
+/// Build the request URI for a gRPC method path of the form
+/// `/package.Service/Method`.
+fn method_uri(path: uri::PathAndQuery) -> Result<Uri, Status> {
+    let valid = path.query().is_none() && {
+        let mut segments = path.path().split('/');
+
+        segments.next() == Some("") &&
+            segments.next().map(|service| !service.is_empty()).unwrap_or(false) &&
+            segments.next().map(|method| !method.is_empty()).unwrap_or(false) &&
+            segments.next().is_none()
+    };
+
+    if !valid {
+        debug!("invalid gRPC method path; path={}", path);
+        return Err(Status::INTERNAL
+            .with_message(format!("invalid gRPC method path: {}", path)));
+    }
+
+    let mut parts = uri::Parts::default();
+    parts.path_and_query = Some(path);
+
+    Uri::from_parts(parts)
+        .map_err(|e| Status::INTERNAL.with_message(format!("invalid gRPC request URI: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Code;
+    use generic::BytesCodec;
+
+    use bytes::Bytes;
+    use futures::{future, Async, Future, Poll};
+    use http::{self, header, StatusCode};
+    use http::header::HeaderValue;
+    use tower::Service;
+    use tower_h2::RecvBody;
+
+    /// Answers every request with the same head and an empty body.
+    #[derive(Debug)]
+    struct FakeHttp {
+        status: StatusCode,
+        headers: http::HeaderMap,
+
+        /// The head of the last request.
+        request: Option<http::request::Parts>,
+    }
+
+    impl FakeHttp {
+        fn new(status: StatusCode) -> Self {
+            FakeHttp {
+                status,
+                headers: http::HeaderMap::new(),
+                request: None,
+            }
+        }
+
+        /// A trailers-only response with the given `grpc-status`.
+        fn trailers_only(code: &'static str) -> Self {
+            let mut http = FakeHttp::new(StatusCode::OK);
+            http.headers.insert("grpc-status", HeaderValue::from_static(code));
+            http.headers.insert("grpc-message", HeaderValue::from_static("no%20such%20thing"));
+            http
+        }
+    }
+
+    impl Service for FakeHttp {
+        type Request = http::Request<BoxBody>;
+        type Response = http::Response<RecvBody>;
+        type Error = ();
+        type Future = future::FutureResult<Self::Response, ()>;
+
+        fn poll_ready(&mut self) -> Poll<(), ()> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, request: Self::Request) -> Self::Future {
+            self.request = Some(request.into_parts().0);
+
+            let mut response = http::Response::new(RecvBody::default());
+            *response.status_mut() = self.status;
+            *response.headers_mut() = self.headers.clone();
+            future::ok(response)
+        }
+    }
+
+    fn path() -> uri::PathAndQuery {
+        uri::PathAndQuery::from_static("/test.Test/Call")
+    }
+
+    fn message() -> Request<Bytes> {
+        Request::new(Bytes::from_static(b"hello"))
+    }
+
+    #[test]
+    fn request_head() {
+        let mut http = FakeHttp::trailers_only("0");
+        let mut grpc = Grpc::new(BytesCodec);
+
+        let _ = grpc.unary(&mut http, message(), path()).wait();
+
+        let head = http.request.expect("request sent");
+        assert_eq!(head.method, http::Method::POST);
+        assert_eq!(head.uri.path(), "/test.Test/Call");
+        assert_eq!(head.headers[header::CONTENT_TYPE], "application/grpc");
+        assert_eq!(head.headers[header::TE], "trailers");
+    }
+
+    #[test]
+    fn interceptor_metadata_is_sent() {
+        let mut http = FakeHttp::trailers_only("0");
+        let mut grpc = Grpc::new(BytesCodec);
+        grpc.set_interceptor(|metadata: &mut MetadataMap| -> Result<(), Status> {
+            metadata.insert("authorization", "Bearer token").unwrap();
+            Ok(())
+        });
+
+        let _ = grpc.unary(&mut http, message(), path()).wait();
+
+        let head = http.request.expect("request sent");
+        assert_eq!(head.headers["authorization"], "Bearer token");
+    }
+
+    #[test]
+    fn interceptor_error_fails_the_call_without_sending_it() {
+        let mut http = FakeHttp::trailers_only("0");
+        let mut grpc = Grpc::new(BytesCodec);
+        grpc.set_interceptor(|_: &mut MetadataMap| -> Result<(), Status> {
+            Err(Status::UNAUTHENTICATED.with_message("no token"))
+        });
+
+        match grpc.unary(&mut http, message(), path()).wait() {
+            Err(::Error::Grpc(status, _)) => {
+                assert_eq!(status.code(), Code::UNAUTHENTICATED);
+                assert_eq!(status.message(), "no token");
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+
+        assert!(http.request.is_none());
+    }
+
+    #[test]
+    fn trailers_only_error() {
+        let mut http = FakeHttp::trailers_only("5");
+        let mut grpc = Grpc::new(BytesCodec);
+
+        match grpc.unary(&mut http, message(), path()).wait() {
+            Err(::Error::Grpc(status, _)) => {
+                assert_eq!(status.code(), Code::NOT_FOUND);
+                assert_eq!(status.message(), "no such thing");
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn trailers_only_ok_is_an_empty_stream() {
+        let mut http = FakeHttp::trailers_only("0");
+        let mut grpc = Grpc::new(BytesCodec);
+
+        let response = grpc.server_streaming(&mut http, message(), path())
+            .wait()
+            .expect("response");
+        let mut messages = response.into_inner();
+
+        assert_eq!(messages.poll().unwrap(), Async::Ready(None));
+        assert!(messages.trailers().is_some());
+    }
+
+    #[test]
+    fn http_error_status() {
+        let mut http = FakeHttp::new(StatusCode::SERVICE_UNAVAILABLE);
+        let mut grpc = Grpc::new(BytesCodec);
+
+        match grpc.unary(&mut http, message(), path()).wait() {
+            Err(::Error::Grpc(status, _)) => assert_eq!(status.code(), Code::UNAVAILABLE),
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use super::streaming;
+use generic::{Decoder, Streaming};
+
+use bytes::Bytes;
+use futures::{Future, Poll};
+use http::Response;
+use tower_h2::Body;
+
+#[derive(Debug)]
+pub struct ResponseFuture<T, U> {
+    inner: streaming::ResponseFuture<T, U>,
+}
+
+impl<T, U> ResponseFuture<T, U> {
+    /// Create a new client-streaming response future.
+    pub(crate) fn new(inner: streaming::ResponseFuture<T, U>) -> Self {
+        ResponseFuture { inner }
+    }
+}
+
+impl<T, U, B> Future for ResponseFuture<T, U>
+where T: Decoder,
+      U: Future<Item = Response<B>>,
+      B: Body,
+      B::Data: Into<Bytes>,
+{
+    type Item = ::Response<Streaming<T, B>>;
+    type Error = ::Error<U::Error>;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        self.inner.poll()
+    }
+}
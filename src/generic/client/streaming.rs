@@ -0,0 +1,213 @@
+use {CancelHandle, Status};
+use deadline::Timer;
+use generic::{Decoder, Streaming};
+use generic::codec::grpc_status;
+use generic::compression::Compression;
+use web::Mode;
+
+use bytes::Bytes;
+use futures::{Future, Poll};
+use http::{HeaderMap, Response, StatusCode};
+use tower_h2::Body;
+
+use std::mem;
+use std::time::Instant;
+
+const GRPC_STATUS_HEADER: &str = "grpc-status";
+
+#[derive(Debug)]
+pub struct ResponseFuture<T, U> {
+    /// The response future, or the error that prevented sending the request.
+    inner: Result<U, Status>,
+    /// Decodes the response messages, until the response is received.
+    decoder: Option<T>,
+    /// Used to decompress the response messages.
+    compression: Compression,
+    /// The largest response message accepted, if limited.
+    max_message_size: Option<usize>,
+    /// Fails the call once its deadline passes.
+    deadline: Timer,
+    /// Reads the response as gRPC-Web, if set.
+    web: Option<Mode>,
+    /// Fails the call once it is canceled, if set.
+    cancel: Option<CancelHandle>,
+}
+
+impl<T, U> ResponseFuture<T, U> {
+    /// Create a new client-streaming response future.
+    pub(crate) fn new(inner: U,
+                      decoder: T,
+                      compression: Compression,
+                      max_message_size: Option<usize>,
+                      deadline: Option<Instant>,
+                      web: Option<Mode>,
+                      cancel: Option<CancelHandle>)
+        -> Self
+    {
+        ResponseFuture {
+            inner: Ok(inner),
+            decoder: Some(decoder),
+            compression,
+            max_message_size,
+            deadline: Timer::new(deadline),
+            web,
+            cancel,
+        }
+    }
+
+    /// Create a response future that fails with the given status without
+    /// sending a request.
+    pub(crate) fn error(status: Status) -> Self {
+        ResponseFuture {
+            inner: Err(status),
+            decoder: None,
+            compression: Compression::none(),
+            max_message_size: None,
+            deadline: Timer::default(),
+            web: None,
+            cancel: None,
+        }
+    }
+}
+
+impl<T, U, B> ResponseFuture<T, U>
+where T: Decoder,
+      U: Future<Item = Response<B>>,
+      B: Body,
+      B::Data: Into<Bytes>,
+{
+    fn poll_response(&mut self) -> Poll<::Response<Streaming<T, B>>, ::Error<U::Error>> {
+        if self.deadline.poll_expired() {
+            debug!("deadline exceeded before the response was received");
+
+            // Dropping the response future resets the HTTP/2 stream.
+            self.inner = Err(Status::DEADLINE_EXCEEDED);
+        }
+
+        if self.cancel.as_ref().map(|cancel| cancel.poll_canceled()).unwrap_or(false) {
+            debug!("call canceled before the response was received");
+
+            // Dropping the response future resets the HTTP/2 stream.
+            self.cancel = None;
+            self.inner = Err(Status::CANCELED);
+        }
+
+        let response = match self.inner {
+            Ok(ref mut inner) => inner.poll().map_err(::Error::Inner),
+            Err(ref status) => return Err(::Error::Grpc(status.clone(), HeaderMap::new())),
+        };
+
+        // Get the response
+        let response = try_ready!(response);
+
+        // Destructure into the head / body
+        let (head, body) = response.into_parts();
+
+        // A trailers-only response carries the status in its headers.
+        let trailers = if head.headers.contains_key(GRPC_STATUS_HEADER) {
+            Some(grpc_status::<U::Error>(head.headers.clone())?)
+        } else {
+            None
+        };
+
+        // Responses that did not come from a gRPC server, such as errors
+        // generated by a proxy, are classified by their HTTP status.
+        if head.status != StatusCode::OK {
+            let status = Status::from_http_status(head.status);
+            return Err(::Error::Grpc(status, head.headers));
+        }
+
+        // The spec requires an unsupported response encoding to be reported
+        // as `INTERNAL`.
+        let decompressor = match self.compression.decompressor(&head.headers) {
+            Ok(decompressor) => decompressor,
+            Err(status) => {
+                let status = Status::INTERNAL.with_message(status.message().to_string());
+                return Err(::Error::Grpc(status, head.headers));
+            }
+        };
+
+        let decoder = self.decoder.take().expect("polled after complete");
+
+        let body = Streaming::new(decoder, body, true)
+            .with_decompressor(decompressor)
+            .with_max_message_size(self.max_message_size)
+            .with_deadline(mem::replace(&mut self.deadline, Timer::default()))
+            .with_cancel(self.cancel.take())
+            .with_completes_call();
+
+        let body = match self.web {
+            Some(mode) => body.with_web_mode(mode),
+            None => body,
+        };
+
+        let body = match trailers {
+            Some(trailers) => body.with_trailers_only(trailers),
+            None => body,
+        };
+
+        let response = Response::from_parts(head, body);
+
+        Ok(::Response::from_http(response).into())
+    }
+}
+
+impl<T, U, B> Future for ResponseFuture<T, U>
+where T: Decoder,
+      U: Future<Item = Response<B>>,
+      B: Body,
+      B::Data: Into<Bytes>,
+{
+    type Item = ::Response<Streaming<T, B>>;
+    type Error = ::Error<U::Error>;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let result = self.poll_response();
+
+        // A call that fails before its response stream is created is over.
+        if result.is_err() {
+            if let Some(ref cancel) = self.cancel {
+                cancel.complete();
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Code;
+    use generic::BytesDecoder;
+
+    use futures::future;
+    use tower_h2::RecvBody;
+
+    #[test]
+    fn cancel_pending_response() {
+        let cancel = CancelHandle::new();
+        let response = ResponseFuture::new(
+            future::empty::<Response<RecvBody>, ()>(),
+            BytesDecoder,
+            Compression::none(),
+            None,
+            None,
+            None,
+            Some(cancel.clone()));
+
+        // Poll once so the call waits for cancellation.
+        let mut response = future::lazy(move || {
+            let mut response = response;
+            assert!(response.poll().expect("poll").is_not_ready());
+            Ok::<_, ()>(response)
+        }).wait().unwrap();
+
+        cancel.cancel();
+
+        match response.poll() {
+            Err(::Error::Grpc(status, _)) => assert_eq!(status.code(), Code::CANCELED),
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use super::client_streaming;
+
+use generic::Decoder;
+
+use bytes::Bytes;
+use futures::{stream, Future, Poll};
+use http::{Response};
+use tower_h2::Body;
+
+#[derive(Debug)]
+pub struct ResponseFuture<T, U, B>
+where T: Decoder,
+{
+    inner: client_streaming::ResponseFuture<T, U, B>,
+}
+
+pub type Once<T> = stream::Once<T, ::Error>;
+
+impl<T, U, B> ResponseFuture<T, U, B>
+where T: Decoder,
+{
+    /// Create a new client-streaming response future.
+    pub(crate) fn new(inner: client_streaming::ResponseFuture<T, U, B>) -> Self {
+        ResponseFuture { inner }
+    }
+}
+
+impl<T, U, B> Future for ResponseFuture<T, U, B>
+where T: Decoder,
+      U: Future<Item = Response<B>>,
+      B: Body,
+      B::Data: Into<Bytes>,
+{
+    type Item = ::Response<T::Item>;
+    type Error = ::Error<U::Error>;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        self.inner.poll()
+    }
+}
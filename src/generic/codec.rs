@@ -1,15 +1,22 @@
-use Status;
+use {CancelHandle, MetadataMap, Status, TrailingMetadata};
+use deadline::Timer;
+use super::compression::{Compressor, MessageCompression};
+use web::{self, Mode, TextDecoder};
 
 use bytes::{Buf, BufMut, BytesMut, Bytes, BigEndian};
 use futures::{Stream, Poll, Async};
 use h2;
-use http::HeaderMap;
-use tower_h2::{self, Body, Data};
+use http::{HeaderMap, HeaderValue};
+use tower_h2::{self, Body};
 
 use std::collections::VecDeque;
+use std::sync::Arc;
 
 use error::ProtocolError;
 
+/// The default limit on the size of a received message, 4 MiB.
+pub(crate) const DEFAULT_MAX_DECODING_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+
 /// Encodes and decodes gRPC message types
 pub trait Codec {
     /// The content-type header for messages using this encoding.
@@ -69,6 +76,41 @@ pub struct Encode<T, U> {
 
     /// Set to true when trailers should be generated.
     return_trailers: bool,
+
+    /// How messages are framed.
+    options: EncodeOptions,
+
+    /// Set to true once the trailers have been returned.
+    done: bool,
+}
+
+/// Options for framing encoded messages.
+#[derive(Debug, Clone, Default)]
+pub struct EncodeOptions {
+    /// Compresses sent messages, if set.
+    pub(crate) compressor: Option<Arc<dyn Compressor>>,
+
+    /// Opts individual messages out of compression.
+    pub(crate) message_compression: Option<MessageCompression>,
+
+    /// The largest message that may be sent, if limited.
+    pub(crate) max_message_size: Option<usize>,
+
+    /// Metadata to send with the trailers.
+    pub(crate) trailers: Option<TrailingMetadata>,
+
+    /// Base64 encodes the body, for gRPC-Web text requests.
+    pub(crate) text: bool,
+
+    /// The content type of a response.
+    pub(crate) content_type: Option<HeaderValue>,
+
+    /// The `grpc-accept-encoding` of a response.
+    pub(crate) accept_encoding: Option<HeaderValue>,
+
+    /// Ends the body with `CANCELED` once the call is canceled, if set. A
+    /// server response body dropped early cancels the call.
+    pub(crate) cancel: Option<CancelHandle>,
 }
 
 #[derive(Debug)]
@@ -90,8 +132,8 @@ pub struct Streaming<T, U = tower_h2::RecvBody> {
     /// The decoder
     decoder: T,
 
-    /// The source of encoded messages
-    inner: U,
+    /// The source of encoded messages, dropped once the call is canceled.
+    inner: Option<U>,
 
     /// buffer
     bufs: BytesList,
@@ -101,6 +143,34 @@ pub struct Streaming<T, U = tower_h2::RecvBody> {
 
     /// Set to true when expecting trailers
     expect_trailers: bool,
+
+    /// Decompresses received messages, if set.
+    decompressor: Option<Arc<dyn Compressor>>,
+
+    /// The largest message that may be received, if limited.
+    max_message_size: Option<usize>,
+
+    /// Fails the stream once the call's deadline passes.
+    deadline: Timer,
+
+    /// An error to yield instead of reading any messages.
+    error: Option<Status>,
+
+    /// Trailing metadata, once received.
+    trailers: Option<MetadataMap>,
+
+    /// Fails the stream once the call is canceled, if set.
+    cancel: Option<CancelHandle>,
+
+    /// Set to true when the end of the stream completes the call, as for a
+    /// client's response.
+    completes_call: bool,
+
+    /// Decodes a gRPC-Web text body, if set.
+    text: Option<TextDecoder>,
+
+    /// Set to true when the trailers may be sent in a gRPC-Web body frame.
+    web: bool,
 }
 
 #[derive(Debug)]
@@ -110,6 +180,9 @@ enum State {
         compression: bool,
         len: usize,
     },
+    ReadTrailers {
+        len: usize,
+    },
     Done,
 }
 
@@ -135,13 +208,15 @@ pub struct BytesList {
 
 impl<T, U> Encode<T, U>
 where T: Encoder<Item = U::Item>,
-      U: Stream,
+      U: Stream<Error = ::Error>,
 {
-    pub(crate) fn new(encoder: T, inner: U, return_trailers: bool) -> Self {
+    pub(crate) fn new(encoder: T, inner: U, options: EncodeOptions, return_trailers: bool) -> Self {
         Encode {
             inner: EncodeInner::Ok { encoder, inner },
             buf: BytesMut::new(),
             return_trailers,
+            options,
+            done: false,
         }
     }
 
@@ -150,13 +225,85 @@ where T: Encoder<Item = U::Item>,
             inner: EncodeInner::Err(status),
             buf: BytesMut::new(),
             return_trailers: true,
+            options: EncodeOptions::default(),
+            done: false,
         }
     }
+
+    /// Encode the next message from the inner stream.
+    ///
+    /// Errors are returned as the status that should be reported to the peer.
+    fn poll_encode(&mut self) -> Poll<Option<Bytes>, Status> {
+        let (encoder, inner) = match self.inner {
+            EncodeInner::Ok { ref mut encoder, ref mut inner } => (encoder, inner),
+            EncodeInner::Err(_) => return Ok(Async::Ready(None)),
+        };
+
+        if self.options.cancel.as_ref().map(|cancel| cancel.poll_canceled()).unwrap_or(false) {
+            trace!("call canceled while sending messages");
+            return Err(Status::CANCELED);
+        }
+
+        let item = match try_ready!(inner.poll().map_err(|e| e.status())) {
+            Some(item) => item,
+            None => return Ok(Async::Ready(None)),
+        };
+
+        self.buf.reserve(5);
+        unsafe { self.buf.advance_mut(5); }
+        encoder.encode(item, &mut EncodeBuf {
+            bytes: &mut self.buf,
+        }).map_err(|e| Status::INTERNAL.with_message(e.to_string()))?;
+
+        let compressed = match self.options.compressor() {
+            Some(compressor) => {
+                let mut dst = Vec::new();
+                compressor.compress(&self.buf[5..], &mut dst)
+                    .map_err(|e| {
+                        Status::INTERNAL.with_message(format!("failed to compress message: {}", e))
+                    })?;
+
+                self.buf.truncate(5);
+                self.buf.extend_from_slice(&dst);
+                true
+            }
+            None => false,
+        };
+
+        // now that we know length, we can write the header
+        let len = self.buf.len() - 5;
+        let max = self.options.max_message_size
+            .unwrap_or(::std::u32::MAX as usize)
+            .min(::std::u32::MAX as usize);
+
+        if len > max {
+            // Discard the oversized message.
+            self.buf.clear();
+
+            return Err(Status::RESOURCE_EXHAUSTED.with_message(format!(
+                "sent message larger than max ({} vs. {})", len, max)));
+        }
+
+        {
+            let mut cursor = ::std::io::Cursor::new(&mut self.buf[..5]);
+            // byte must be written, reserve doesn't auto-zero
+            cursor.put_u8(compressed as u8);
+            cursor.put_u32::<BigEndian>(len as u32);
+        }
+
+        let message = self.buf.split_to(len + 5).freeze();
+
+        if self.options.text {
+            return Ok(Async::Ready(Some(Mode::Text.encode(message))));
+        }
+
+        Ok(Async::Ready(Some(message)))
+    }
 }
 
 impl<T, U> tower_h2::Body for Encode<T, U>
 where T: Encoder<Item = U::Item>,
-      U: Stream,
+      U: Stream<Error = ::Error>,
 {
     type Data = Bytes;
 
@@ -165,32 +312,21 @@ where T: Encoder<Item = U::Item>,
     }
 
     fn poll_data(&mut self) -> Poll<Option<Self::Data>, h2::Error> {
-        match self.inner {
-            EncodeInner::Ok { ref mut inner, ref mut encoder } => {
-                let item = try_ready!(inner.poll().map_err(|_| h2_err()));
-
-                if let Some(item) = item {
-                    self.buf.reserve(5);
-                    unsafe { self.buf.advance_mut(5); }
-                    encoder.encode(item, &mut EncodeBuf {
-                        bytes: &mut self.buf,
-                    }).map_err(|_| h2_err())?;
-
-                    // now that we know length, we can write the header
-                    let len = self.buf.len() - 5;
-                    assert!(len <= ::std::u32::MAX as usize);
-                    {
-                        let mut cursor = ::std::io::Cursor::new(&mut self.buf[..5]);
-                        cursor.put_u8(0); // byte must be 0, reserve doesn't auto-zero
-                        cursor.put_u32::<BigEndian>(len as u32);
-                    }
-
-                    Ok(Async::Ready(Some(self.buf.split_to(len + 5).freeze())))
-                } else {
+        match self.poll_encode() {
+            Ok(ready) => Ok(ready),
+            Err(status) => {
+                debug!("error encoding message stream; status={:?}", status);
+
+                if self.return_trailers {
+                    // End the body and report the error in the trailers.
+                    self.inner = EncodeInner::Err(status);
                     Ok(Async::Ready(None))
+                } else {
+                    // Without trailers the only way to signal the error is
+                    // to reset the stream.
+                    Err(status.into())
                 }
             }
-            _ => Ok(Async::Ready(None)),
         }
     }
 
@@ -199,72 +335,223 @@ where T: Encoder<Item = U::Item>,
             return Ok(Async::Ready(None));
         }
 
-        let mut map = HeaderMap::new();
+        self.done = true;
 
-        let status = match self.inner {
-            EncodeInner::Ok { .. } => Status::OK.to_header_value(),
-            EncodeInner::Err(ref status) => status.to_header_value(),
+        if let Some(ref cancel) = self.options.cancel {
+            cancel.complete();
+        }
+
+        let mut map = match self.options.trailers {
+            Some(ref trailers) => trailers.take().into_headers(),
+            None => HeaderMap::new(),
         };
 
-        // Success
-        map.insert("grpc-status", status);
+        match self.inner {
+            EncodeInner::Ok { .. } => Status::OK.add_header(&mut map),
+            EncodeInner::Err(ref status) => status.add_header(&mut map),
+        }
 
         Ok(Some(map).into())
     }
 }
 
+impl<T, U> Drop for Encode<T, U> {
+    fn drop(&mut self) {
+        // A server drops a response body before its trailers are sent when
+        // the client resets the stream.
+        if self.return_trailers && !self.done {
+            if let Some(ref cancel) = self.options.cancel {
+                trace!("response body dropped before the trailers were sent");
+                cancel.cancel();
+            }
+        }
+    }
+}
+
+// ===== impl EncodeOptions =====
+
+impl EncodeOptions {
+    /// The compressor for the next message, unless it has opted out.
+    fn compressor(&self) -> Option<&Arc<dyn Compressor>> {
+        let enabled = self.message_compression.as_ref()
+            .map(|c| c.is_enabled())
+            .unwrap_or(true);
+
+        if enabled {
+            self.compressor.as_ref()
+        } else {
+            None
+        }
+    }
+}
+
 // ===== impl Streaming =====
 
 impl<T, U> Streaming<T, U>
 where T: Decoder,
-      U: Body<Data = Data>,
+      U: Body,
+      U::Data: Into<Bytes>,
 {
     pub(crate) fn new(decoder: T, inner: U, expect_trailers: bool) -> Self {
         Streaming {
             decoder,
-            inner,
+            inner: Some(inner),
             bufs: BytesList {
                 bufs: VecDeque::new(),
             },
             state: State::ReadHeader,
             expect_trailers,
+            decompressor: None,
+            max_message_size: Some(DEFAULT_MAX_DECODING_MESSAGE_SIZE),
+            deadline: Timer::default(),
+            error: None,
+            trailers: None,
+            cancel: None,
+            completes_call: false,
+            text: None,
+            web: false,
         }
     }
 
+    /// Limit the size of received messages, or remove the limit with `None`.
+    pub(crate) fn with_max_message_size(mut self, max_message_size: Option<usize>) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+
+    /// Decompress received messages with `decompressor`.
+    pub(crate) fn with_decompressor(mut self, decompressor: Option<Arc<dyn Compressor>>) -> Self {
+        self.decompressor = decompressor;
+        self
+    }
+
+    /// Fail the stream with `DEADLINE_EXCEEDED` once `deadline` fires.
+    pub(crate) fn with_deadline(mut self, deadline: Timer) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    /// Fail the stream with `CANCELED` once the call is canceled, and cancel
+    /// the call if the stream fails.
+    pub(crate) fn with_cancel(mut self, cancel: Option<CancelHandle>) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Complete the call once the stream ends, releasing the tasks waiting
+    /// for it to be canceled.
+    pub(crate) fn with_completes_call(mut self) -> Self {
+        self.completes_call = true;
+        self
+    }
+
+    /// Fail the stream with `status` without reading any messages.
+    pub(crate) fn with_error(mut self, status: Status) -> Self {
+        self.error = Some(status);
+        self
+    }
+
+    /// End the stream without reading any messages, for a response that
+    /// carried its status in the headers.
+    pub(crate) fn with_trailers_only(mut self, trailers: HeaderMap) -> Self {
+        self.state = State::Done;
+        self.expect_trailers = false;
+        self.trailers = Some(MetadataMap::from_headers(trailers));
+        self
+    }
+
+    /// Read a gRPC-Web body, decoding base64 in the text mode.
+    pub(crate) fn with_web_mode(mut self, mode: Mode) -> Self {
+        if mode == Mode::Text {
+            self.text = Some(TextDecoder::default());
+        }
+
+        self.web = true;
+        self
+    }
+
+    /// The trailing metadata sent by the peer.
+    ///
+    /// Only available once the stream has ended successfully. On failure,
+    /// the trailers are returned with the error.
+    pub fn trailers(&self) -> Option<&MetadataMap> {
+        self.trailers.as_ref()
+    }
+
+    /// Cancel the call if the stream failed, whether the peer reset it or
+    /// the connection was lost.
+    fn cancel_on_reset<V>(&self, result: Result<V, h2::Error>) -> Result<V, h2::Error> {
+        if let Err(ref e) = result {
+            if let Some(ref cancel) = self.cancel {
+                trace!("stream failed; canceling the call; err={:?}", e);
+                cancel.cancel();
+            }
+        }
+
+        result
+    }
+
     fn decode(&mut self) -> Result<Option<T::Item>, ::Error> {
         if let State::ReadHeader = self.state {
             if self.bufs.remaining() < 5 {
                 return Ok(None);
             }
 
-            let is_compressed = match self.bufs.get_u8() {
-                0 => false,
-                1 => {
-                    trace!("message compressed, compression not supported yet");
-                    return Err(::Error::Protocol(ProtocolError::UnsupportedCompressionFlag(1)));
-                },
-                f => {
-                    trace!("unexpected compression flag");
-                    return Err(::Error::Protocol(ProtocolError::UnsupportedCompressionFlag(f)));
-                }
-            };
+            let flag = self.bufs.get_u8();
             let len = self.bufs.get_u32::<BigEndian>() as usize;
 
-            self.state = State::ReadBody {
-                compression: is_compressed,
-                len,
+            // gRPC-Web responses end with the trailers in the body.
+            if flag & web::TRAILERS_FLAG != 0 && self.web && self.expect_trailers {
+                // The trailers are buffered whole, just like a message.
+                self.check_message_size(len)?;
+
+                self.state = State::ReadTrailers { len };
+            } else {
+                let is_compressed = match flag {
+                    0 => false,
+                    1 if self.decompressor.is_some() => true,
+                    1 => {
+                        trace!("message compressed, but no grpc-encoding was negotiated");
+                        return Err(::Error::Protocol(ProtocolError::UnsupportedCompressionFlag(1)));
+                    },
+                    f => {
+                        trace!("unexpected compression flag");
+                        return Err(::Error::Protocol(ProtocolError::UnsupportedCompressionFlag(f)));
+                    }
+                };
+
+                // Check the declared length before buffering the message.
+                self.check_message_size(len)?;
+
+                self.state = State::ReadBody {
+                    compression: is_compressed,
+                    len,
+                }
             }
         }
 
-        if let State::ReadBody { len, .. } = self.state {
+        if let State::ReadBody { compression, len } = self.state {
             if self.bufs.remaining() < len {
                 return Ok(None);
             }
 
-            match self.decoder.decode(&mut DecodeBuf {
-                bufs: &mut self.bufs,
-                len,
-            }) {
+            let decoded = if compression {
+                let mut bufs = self.decompress(len)?;
+                let len = bufs.remaining();
+                self.check_message_size(len)?;
+
+                self.decoder.decode(&mut DecodeBuf {
+                    bufs: &mut bufs,
+                    len,
+                })
+            } else {
+                self.decoder.decode(&mut DecodeBuf {
+                    bufs: &mut self.bufs,
+                    len,
+                })
+            };
+
+            match decoded {
                 Ok(msg) => {
                     self.state = State::ReadHeader;
                     return Ok(Some(msg));
@@ -275,18 +562,94 @@ where T: Decoder,
             }
         }
 
+        if let State::ReadTrailers { len } = self.state {
+            if self.bufs.remaining() < len {
+                return Ok(None);
+            }
+
+            let mut block = vec![0; len];
+            self.bufs.copy_to_slice(&mut block);
+
+            let trailers = web::decode_trailers(&block).map_err(::Error::Protocol)?;
+
+            self.state = State::Done;
+            self.expect_trailers = false;
+
+            let trailers = grpc_status(trailers)?;
+            self.trailers = Some(MetadataMap::from_headers(trailers));
+        }
+
         Ok(None)
     }
-}
 
-impl<T, U> Stream for Streaming<T, U>
-where T: Decoder,
-      U: Body<Data = Data>,
-{
-    type Item = T::Item;
-    type Error = ::Error;
+    fn check_message_size(&mut self, len: usize) -> Result<(), ::Error> {
+        match self.max_message_size {
+            Some(max) if len > max => {
+                trace!("received message too large; len={}; max={}", len, max);
+
+                // Nothing more is read from the stream.
+                self.state = State::Done;
+                self.expect_trailers = false;
+
+                let status = Status::RESOURCE_EXHAUSTED.with_message(format!(
+                    "received message larger than max ({} vs. {})", len, max));
+                Err(::Error::Grpc(status, HeaderMap::new()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Decompress the next `len` bytes of the buffer.
+    fn decompress(&mut self, len: usize) -> Result<BytesList, ::Error> {
+        let mut src = vec![0; len];
+        self.bufs.copy_to_slice(&mut src);
+
+        let mut dst = Vec::new();
+
+        // Inflate at most one byte past the limit, enough for the caller to
+        // reject the message without decompressing all of it.
+        let limit = self.max_message_size
+            .map(|max| max.saturating_add(1))
+            .unwrap_or(::std::usize::MAX);
+
+        if let Some(ref decompressor) = self.decompressor {
+            decompressor.decompress(&src, &mut dst, limit)
+                .map_err(|e| {
+                    let status = Status::INTERNAL
+                        .with_message(format!("failed to decompress message: {}", e));
+                    ::Error::Grpc(status, HeaderMap::new())
+                })?;
+        }
+
+        let mut bufs = VecDeque::new();
+        bufs.push_back(Bytes::from(dst));
+
+        Ok(BytesList { bufs })
+    }
+
+    /// Poll for the next message, or the end of the stream.
+    fn poll_messages(&mut self) -> Poll<Option<T::Item>, ::Error> {
+        if self.deadline.poll_expired() {
+            trace!("deadline exceeded while receiving messages");
+            self.error = Some(Status::DEADLINE_EXCEEDED);
+        }
+
+        if self.cancel.as_ref().map(|cancel| cancel.poll_canceled()).unwrap_or(false) {
+            trace!("call canceled while receiving messages");
+            self.cancel = None;
+            self.error = Some(Status::CANCELED);
+
+            // Dropping the body resets the stream, once the request body is
+            // done with it too.
+            self.inner = None;
+        }
+
+        if let Some(status) = self.error.take() {
+            self.state = State::Done;
+            self.expect_trailers = false;
+            return Err(::Error::Grpc(status, HeaderMap::new()));
+        }
 
-    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
         loop {
             if let State::Done = self.state {
                 break;
@@ -294,15 +657,33 @@ where T: Decoder,
 
             match self.decode()? {
                 Some(val) => return Ok(Async::Ready(Some(val))),
-                None => (),
+                // A gRPC-Web trailers frame ends the stream.
+                None => if let State::Done = self.state {
+                    continue;
+                },
             }
 
-            let chunk = try_ready!(self.inner.poll_data());
+            let chunk = match self.inner {
+                Some(ref mut inner) => inner.poll_data(),
+                None => Ok(Async::Ready(None)),
+            };
+            let chunk = try_ready!(self.cancel_on_reset(chunk));
 
             if let Some(data) = chunk {
-                self.bufs.bufs.push_back(data.into());
+                let data: Bytes = data.into();
+
+                let data = match self.text {
+                    Some(ref mut text) => text.decode(&data).map_err(::Error::Protocol)?,
+                    None => data,
+                };
+
+                self.bufs.bufs.push_back(data);
             } else {
-                if self.bufs.has_remaining() {
+                let partial_text = self.text.as_ref()
+                    .map(|text| !text.is_empty())
+                    .unwrap_or(false);
+
+                if self.bufs.has_remaining() || partial_text {
                     trace!("unexpected EOF decoding stream");
                     return Err(::Error::Protocol(ProtocolError::UnexpectedEof))
                 } else {
@@ -313,8 +694,15 @@ where T: Decoder,
         }
 
         if self.expect_trailers {
-            if let Some(trailers) = try_ready!(self.inner.poll_trailers()) {
-                grpc_status(trailers)?;
+            let trailers = match self.inner {
+                Some(ref mut inner) => try_ready!(inner.poll_trailers()),
+                None => None,
+            };
+
+            if let Some(trailers) = trailers {
+                let trailers = grpc_status(trailers)?;
+                self.trailers = Some(MetadataMap::from_headers(trailers));
+                self.expect_trailers = false;
                 Ok(Async::Ready(None))
             } else {
                 trace!("receive body ended without trailers");
@@ -326,6 +714,32 @@ where T: Decoder,
     }
 }
 
+impl<T, U> Stream for Streaming<T, U>
+where T: Decoder,
+      U: Body,
+      U::Data: Into<Bytes>,
+{
+    type Item = T::Item;
+    type Error = ::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let result = self.poll_messages();
+
+        let ended = match result {
+            Ok(Async::Ready(None)) | Err(_) => true,
+            _ => false,
+        };
+
+        if ended && self.completes_call {
+            if let Some(ref cancel) = self.cancel {
+                cancel.complete();
+            }
+        }
+
+        result
+    }
+}
+
 // ===== impl EncodeBuf =====
 
 impl<'a> EncodeBuf<'a> {
@@ -426,15 +840,11 @@ impl Buf for BytesList {
 
 // ===== impl utils =====
 
-fn h2_err() -> h2::Error {
-    unimplemented!("EncodingBody map_err")
-}
-
-fn grpc_status(mut trailers: HeaderMap) -> Result<(), ::Error> {
-    if let Some(status) = trailers.remove("grpc-status") {
-        let status = Status::from_bytes(status.as_ref());
+/// Check the `grpc-status` of `trailers`, failing unless it is `OK`.
+pub(crate) fn grpc_status<E>(trailers: HeaderMap) -> Result<HeaderMap, ::Error<E>> {
+    if let Some(status) = Status::from_header_map(&trailers) {
         if status.code() == ::Code::OK {
-            Ok(())
+            Ok(trailers)
         } else {
             Err(::Error::Grpc(status, trailers))
         }
@@ -443,3 +853,317 @@ fn grpc_status(mut trailers: HeaderMap) -> Result<(), ::Error> {
         Err(::Error::Protocol(ProtocolError::MissingTrailers))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Code;
+    use generic::BytesDecoder;
+    use test_util::{frame, frame_with_flag, Reverse, TestBody};
+
+    use futures::{future, stream, Future};
+
+    use std::io;
+
+    /// Writes each message out verbatim.
+    #[derive(Debug)]
+    struct BytesEncoder;
+
+    /// Fails to encode every message.
+    #[derive(Debug)]
+    struct FailingEncoder;
+
+    impl Encoder for BytesEncoder {
+        type Item = Bytes;
+
+        fn encode(&mut self, item: Bytes, buf: &mut EncodeBuf) -> Result<(), ::Error> {
+            buf.reserve(item.len());
+            buf.put_slice(&item[..]);
+            Ok(())
+        }
+    }
+
+    impl Encoder for FailingEncoder {
+        type Item = Bytes;
+
+        fn encode(&mut self, _: Bytes, _: &mut EncodeBuf) -> Result<(), ::Error> {
+            Err(::Error::Protocol(ProtocolError::Internal))
+        }
+    }
+
+    fn trailers_status<B: Body>(body: &mut B) -> Status {
+        match body.poll_trailers().expect("poll_trailers") {
+            Async::Ready(Some(trailers)) => {
+                Status::from_header_map(&trailers).expect("trailers missing grpc-status")
+            }
+            trailers => panic!("unexpected trailers: {:?}", trailers),
+        }
+    }
+
+    #[test]
+    fn stream_error_is_sent_as_trailers() {
+        let messages = stream::iter_result(vec![
+            Ok(Bytes::from_static(b"hello")),
+            Err(::Error::Grpc(Status::NOT_FOUND.with_message("no such thing"), HeaderMap::new())),
+        ]);
+        let mut encode = Encode::new(BytesEncoder, messages, EncodeOptions::default(), true);
+
+        let frame = encode.poll_data().expect("first message");
+        assert_eq!(frame, Async::Ready(Some(Bytes::from_static(b"\0\0\0\0\x05hello"))));
+
+        let end = encode.poll_data().expect("stream error ends the body");
+        assert_eq!(end, Async::Ready(None));
+
+        let status = trailers_status(&mut encode);
+        assert_eq!(status.code(), Code::NOT_FOUND);
+        assert_eq!(status.message(), "no such thing");
+    }
+
+    #[test]
+    fn inner_stream_error_is_unknown() {
+        let messages = stream::iter_result::<_, Bytes, _>(vec![Err(::Error::Inner(()))]);
+        let mut encode = Encode::new(BytesEncoder, messages, EncodeOptions::default(), true);
+
+        assert_eq!(encode.poll_data().unwrap(), Async::Ready(None));
+        assert_eq!(trailers_status(&mut encode).code(), Code::UNKNOWN);
+    }
+
+    #[test]
+    fn encoder_error_is_internal() {
+        let messages = stream::iter_ok::<_, ::Error>(vec![Bytes::from_static(b"hello")]);
+        let mut encode = Encode::new(FailingEncoder, messages, EncodeOptions::default(), true);
+
+        assert_eq!(encode.poll_data().unwrap(), Async::Ready(None));
+        assert_eq!(trailers_status(&mut encode).code(), Code::INTERNAL);
+    }
+
+    #[test]
+    fn trailing_metadata_is_sent_with_grpc_status() {
+        let trailers = TrailingMetadata::new();
+        let mut options = EncodeOptions::default();
+        options.trailers = Some(trailers.clone());
+
+        let messages = stream::iter_ok::<_, ::Error>(vec![Bytes::from_static(b"hello")]);
+        let mut encode = Encode::new(BytesEncoder, messages, options, true);
+
+        // A handler may set trailing metadata while the response is sent.
+        trailers.update(|metadata| metadata.insert("x-checksum", "abc").unwrap());
+
+        assert_eq!(encode.poll_data().unwrap(), Async::Ready(Some(frame(b"hello"))));
+        assert_eq!(encode.poll_data().unwrap(), Async::Ready(None));
+
+        let map = match encode.poll_trailers().expect("poll_trailers") {
+            Async::Ready(Some(map)) => map,
+            trailers => panic!("unexpected trailers: {:?}", trailers),
+        };
+        assert_eq!(map["grpc-status"], "0");
+        assert_eq!(map["x-checksum"], "abc");
+    }
+
+    #[test]
+    fn trailers_are_available_once_the_stream_ends() {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", HeaderValue::from_static("0"));
+        trailers.insert("x-checksum", HeaderValue::from_static("abc"));
+
+        let body = TestBody::new(vec![frame(b"hello")]).with_trailers(trailers);
+        let mut messages = Streaming::new(BytesDecoder, body, true);
+
+        assert_eq!(messages.poll().unwrap(), Async::Ready(Some(Bytes::from_static(b"hello"))));
+        assert!(messages.trailers().is_none());
+
+        assert_eq!(messages.poll().unwrap(), Async::Ready(None));
+        assert_eq!(messages.trailers().expect("trailers").get("x-checksum"), Some("abc"));
+    }
+
+    fn compressed_stream(frames: Vec<Bytes>) -> Streaming<BytesDecoder, TestBody> {
+        Streaming::new(BytesDecoder, TestBody::new(frames), false)
+            .with_decompressor(Some(Arc::new(Reverse)))
+    }
+
+    #[test]
+    fn compressed_messages_are_decompressed() {
+        let mut messages = compressed_stream(vec![
+            frame_with_flag(1, b"olleh"),
+            frame(b"plain"),
+        ]);
+
+        assert_eq!(messages.poll().unwrap(), Async::Ready(Some(Bytes::from_static(b"hello"))));
+        assert_eq!(messages.poll().unwrap(), Async::Ready(Some(Bytes::from_static(b"plain"))));
+        assert_eq!(messages.poll().unwrap(), Async::Ready(None));
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn compressed_message_inflating_past_limit_is_resource_exhausted() {
+        use generic::compression::Gzip;
+
+        // A megabyte of zeros compresses to about a kilobyte.
+        let mut compressed = Vec::new();
+        Gzip.compress(&vec![0; 1024 * 1024], &mut compressed).expect("compress");
+        let limit = compressed.len() + 1;
+
+        let body = TestBody::new(vec![frame_with_flag(1, &compressed)]);
+        let mut messages = Streaming::new(BytesDecoder, body, false)
+            .with_decompressor(Some(Arc::new(Gzip)))
+            .with_max_message_size(Some(limit));
+
+        match messages.poll() {
+            Err(::Error::Grpc(status, _)) => assert_eq!(status.code(), Code::RESOURCE_EXHAUSTED),
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn compressed_flag_without_grpc_encoding_is_an_error() {
+        let body = TestBody::new(vec![frame_with_flag(1, b"olleh")]);
+        let mut messages = Streaming::new(BytesDecoder, body, false);
+
+        match messages.poll() {
+            Err(::Error::Protocol(ProtocolError::UnsupportedCompressionFlag(1))) => {}
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn unknown_compression_flag_is_an_error() {
+        let mut messages = compressed_stream(vec![frame_with_flag(2, b"hello")]);
+
+        match messages.poll() {
+            Err(::Error::Protocol(ProtocolError::UnsupportedCompressionFlag(2))) => {}
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn sent_messages_are_compressed_unless_opted_out() {
+        let handle = MessageCompression::new();
+
+        let mut options = EncodeOptions::default();
+        options.compressor = Some(Arc::new(Reverse));
+        options.message_compression = Some(handle.clone());
+
+        let messages = stream::iter_ok::<_, ::Error>(vec![
+            Bytes::from_static(b"hello"),
+            Bytes::from_static(b"plain"),
+        ]);
+        let mut encode = Encode::new(BytesEncoder, messages, options, true);
+
+        let first = encode.poll_data().expect("first message");
+        assert_eq!(first, Async::Ready(Some(frame_with_flag(1, b"olleh"))));
+
+        handle.set_enabled(false);
+
+        let second = encode.poll_data().expect("second message");
+        assert_eq!(second, Async::Ready(Some(frame(b"plain"))));
+    }
+
+    fn web_trailers(extra: &'static str) -> Bytes {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", HeaderValue::from_static("0"));
+        trailers.insert("x-extra", HeaderValue::from_static(extra));
+        web::encode_trailers(&trailers)
+    }
+
+    #[test]
+    fn web_trailers_end_the_stream() {
+        let body = TestBody::new(vec![frame(b"hello"), web_trailers("1")]);
+        let mut messages = Streaming::new(BytesDecoder, body, true)
+            .with_web_mode(Mode::Binary);
+
+        assert_eq!(messages.poll().unwrap(), Async::Ready(Some(Bytes::from_static(b"hello"))));
+        assert_eq!(messages.poll().unwrap(), Async::Ready(None));
+        assert_eq!(messages.trailers().unwrap().get("x-extra"), Some("1"));
+    }
+
+    #[test]
+    fn web_text_body_split_across_chunks() {
+        // Each frame is encoded separately, so padding ends the first one
+        // in the middle of the body.
+        let mut text = ::base64::encode(&frame(b"hello"));
+        text.push_str(&::base64::encode(&web_trailers("1")));
+        assert!(text[..16].ends_with("=="));
+
+        let chunks = text.as_bytes()
+            .chunks(3)
+            .map(Bytes::from)
+            .collect::<Vec<_>>();
+        let mut messages = Streaming::new(BytesDecoder, TestBody::new(chunks), true)
+            .with_web_mode(Mode::Text);
+
+        assert_eq!(messages.poll().unwrap(), Async::Ready(Some(Bytes::from_static(b"hello"))));
+        assert_eq!(messages.poll().unwrap(), Async::Ready(None));
+        assert_eq!(messages.trailers().unwrap().get("x-extra"), Some("1"));
+    }
+
+    #[test]
+    fn web_trailers_over_limit_are_resource_exhausted() {
+        let trailers = web_trailers("a value longer than the limit");
+        let mut messages = Streaming::new(BytesDecoder, TestBody::new(vec![trailers]), true)
+            .with_web_mode(Mode::Binary)
+            .with_max_message_size(Some(16));
+
+        match messages.poll() {
+            Err(::Error::Grpc(status, _)) => assert_eq!(status.code(), Code::RESOURCE_EXHAUSTED),
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    fn reset_cancels(error: h2::Error) {
+        let cancel = CancelHandle::new();
+        let body = TestBody::new(vec![frame(b"hello")]).with_error(error);
+        let mut messages = Streaming::new(BytesDecoder, body, false)
+            .with_cancel(Some(cancel.clone()));
+        let canceled = ::Canceled::new(Some(cancel.clone()), None);
+
+        // The stream is polled within a task.
+        future::lazy(move || {
+            assert_eq!(messages.poll().unwrap(), Async::Ready(Some(Bytes::from_static(b"hello"))));
+            assert!(!cancel.is_canceled());
+
+            assert!(messages.poll().is_err());
+            Ok::<_, ()>(())
+        }).wait().unwrap();
+
+        assert_eq!(canceled.wait(), Ok(()));
+    }
+
+    #[test]
+    fn reset_with_cancel_cancels_the_call() {
+        reset_cancels(h2::Reason::CANCEL.into());
+    }
+
+    #[test]
+    fn any_stream_error_cancels_the_call() {
+        reset_cancels(h2::Reason::INTERNAL_ERROR.into());
+        reset_cancels(io::Error::new(io::ErrorKind::ConnectionReset, "reset").into());
+    }
+
+    #[test]
+    fn cancel_drops_the_body() {
+        let cancel = CancelHandle::new();
+        let body = TestBody::new(vec![frame(b"hello")]);
+        let mut messages = Streaming::new(BytesDecoder, body, true)
+            .with_cancel(Some(cancel.clone()));
+
+        cancel.cancel();
+
+        match messages.poll() {
+            Err(::Error::Grpc(status, _)) => assert_eq!(status.code(), Code::CANCELED),
+            res => panic!("unexpected result: {:?}", res),
+        }
+        assert!(messages.inner.is_none());
+        assert_eq!(messages.poll().unwrap(), Async::Ready(None));
+    }
+
+    #[test]
+    fn stream_error_without_trailers_resets() {
+        let messages = stream::iter_result::<_, Bytes, _>(vec![
+            Err(::Error::Grpc(Status::CANCELED, HeaderMap::new())),
+        ]);
+        let mut encode = Encode::new(BytesEncoder, messages, EncodeOptions::default(), false);
+
+        let err = encode.poll_data().expect_err("request body should reset");
+        assert_eq!(err.reason(), Some(h2::Reason::CANCEL));
+    }
+}
@@ -0,0 +1,422 @@
+//! gRPC message compression.
+//!
+//! The algorithm used for a call is negotiated with the `grpc-encoding` and
+//! `grpc-accept-encoding` headers. Each message is then compressed
+//! individually, and flagged as such in its length-prefixed frame.
+
+use Status;
+
+use http::HeaderMap;
+use http::header::HeaderValue;
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub(crate) const ENCODING_HEADER: &str = "grpc-encoding";
+pub(crate) const ACCEPT_ENCODING_HEADER: &str = "grpc-accept-encoding";
+
+/// The encoding name that means "no compression".
+pub(crate) const IDENTITY: &str = "identity";
+
+/// Compresses and decompresses message payloads.
+pub trait Compressor: fmt::Debug + Send + Sync {
+    /// The name of the algorithm, as sent in `grpc-encoding`.
+    fn name(&self) -> &'static str;
+
+    /// Compress `src`, appending the output to `dst`.
+    fn compress(&self, src: &[u8], dst: &mut Vec<u8>) -> io::Result<()>;
+
+    /// Decompress `src`, appending the output to `dst`.
+    ///
+    /// At most `limit` bytes are appended. Implementations must stop
+    /// inflating once the limit is reached, rather than decompressing the
+    /// whole message first.
+    fn decompress(&self, src: &[u8], dst: &mut Vec<u8>, limit: usize) -> io::Result<()>;
+}
+
+/// The `gzip` compression algorithm.
+#[cfg(feature = "gzip")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Gzip;
+
+/// The `deflate` compression algorithm.
+#[cfg(feature = "deflate")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Deflate;
+
+/// The compression algorithms available to a client or server.
+///
+/// By default, every algorithm enabled by a cargo feature can be used to
+/// decompress received messages, but sent messages are not compressed.
+#[derive(Debug, Clone)]
+pub struct Compression {
+    /// Registered algorithms, in order of preference.
+    compressors: Vec<Arc<dyn Compressor>>,
+
+    /// The name of the algorithm used to compress sent messages.
+    send: Option<&'static str>,
+}
+
+/// An error returned when selecting a compression algorithm that is not
+/// registered.
+#[derive(Debug)]
+pub struct UnregisteredAlgorithm {
+    name: &'static str,
+}
+
+/// A handle for turning compression on or off for the individual messages
+/// of a call.
+///
+/// Compression of a message is decided when the message is sent, so a
+/// handle shared with the code producing a stream of messages can opt out
+/// of compressing some of them. Disabling the handle before any message is
+/// sent opts the entire call out of compression.
+#[derive(Debug, Clone)]
+pub struct MessageCompression {
+    enabled: Arc<AtomicBool>,
+}
+
+// ===== impl Gzip =====
+
+#[cfg(feature = "gzip")]
+impl Compressor for Gzip {
+    fn name(&self) -> &'static str {
+        "gzip"
+    }
+
+    fn compress(&self, src: &[u8], dst: &mut Vec<u8>) -> io::Result<()> {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(dst, ::flate2::Compression::default());
+        encoder.write_all(src)?;
+        encoder.finish()?;
+        Ok(())
+    }
+
+    fn decompress(&self, src: &[u8], dst: &mut Vec<u8>, limit: usize) -> io::Result<()> {
+        use flate2::read::GzDecoder;
+        use std::io::Read;
+
+        GzDecoder::new(src).take(limit as u64).read_to_end(dst)?;
+        Ok(())
+    }
+}
+
+// ===== impl Deflate =====
+
+#[cfg(feature = "deflate")]
+impl Compressor for Deflate {
+    fn name(&self) -> &'static str {
+        "deflate"
+    }
+
+    fn compress(&self, src: &[u8], dst: &mut Vec<u8>) -> io::Result<()> {
+        use flate2::write::ZlibEncoder;
+        use std::io::Write;
+
+        let mut encoder = ZlibEncoder::new(dst, ::flate2::Compression::default());
+        encoder.write_all(src)?;
+        encoder.finish()?;
+        Ok(())
+    }
+
+    fn decompress(&self, src: &[u8], dst: &mut Vec<u8>, limit: usize) -> io::Result<()> {
+        use flate2::read::ZlibDecoder;
+        use std::io::Read;
+
+        ZlibDecoder::new(src).take(limit as u64).read_to_end(dst)?;
+        Ok(())
+    }
+}
+
+// ===== impl Compression =====
+
+impl Compression {
+    /// Returns a new `Compression` with the algorithms enabled by cargo
+    /// features registered.
+    pub fn new() -> Self {
+        #[allow(unused_mut)]
+        let mut compression = Compression::none();
+
+        #[cfg(feature = "gzip")]
+        compression.register(Gzip);
+
+        #[cfg(feature = "deflate")]
+        compression.register(Deflate);
+
+        compression
+    }
+
+    /// Returns a new `Compression` without any algorithms registered.
+    pub fn none() -> Self {
+        Compression {
+            compressors: vec![],
+            send: None,
+        }
+    }
+
+    /// Register a compression algorithm.
+    ///
+    /// A previously registered algorithm with the same name is replaced.
+    pub fn register<C>(&mut self, compressor: C) -> &mut Self
+    where C: Compressor + 'static,
+    {
+        self.compressors.retain(|c| c.name() != compressor.name());
+        self.compressors.push(Arc::new(compressor));
+        self
+    }
+
+    /// Compress sent messages with the registered algorithm named `name`.
+    ///
+    /// Passing `None` disables compression of sent messages. Fails if no
+    /// algorithm with that name is registered.
+    pub fn send(&mut self, name: Option<&'static str>) -> Result<&mut Self, UnregisteredAlgorithm> {
+        if let Some(name) = name {
+            if self.get(name).is_none() {
+                return Err(UnregisteredAlgorithm { name });
+            }
+        }
+
+        self.send = name;
+        Ok(self)
+    }
+
+    fn get(&self, name: &str) -> Option<Arc<dyn Compressor>> {
+        self.compressors.iter()
+            .find(|c| c.name() == name)
+            .cloned()
+    }
+
+    /// The compressor used for sent messages, if any.
+    pub(crate) fn send_compressor(&self) -> Option<Arc<dyn Compressor>> {
+        self.send.and_then(|name| self.get(name))
+    }
+
+    /// The `grpc-accept-encoding` value listing the registered algorithms.
+    pub(crate) fn accept_encoding(&self) -> Option<HeaderValue> {
+        if self.compressors.is_empty() {
+            return None;
+        }
+
+        let names: Vec<_> = self.compressors.iter()
+            .map(|c| c.name())
+            .collect();
+
+        HeaderValue::from_str(&names.join(",")).ok()
+    }
+
+    /// Get the compressor for the peer's `grpc-encoding`.
+    ///
+    /// An encoding that is not registered results in an `UNIMPLEMENTED`
+    /// status, as required by the gRPC spec.
+    pub(crate) fn decompressor(&self, headers: &HeaderMap)
+        -> Result<Option<Arc<dyn Compressor>>, Status>
+    {
+        let encoding = match headers.get(ENCODING_HEADER) {
+            Some(encoding) => encoding,
+            None => return Ok(None),
+        };
+
+        match encoding.to_str() {
+            Ok(IDENTITY) => Ok(None),
+            Ok(name) => {
+                self.get(name)
+                    .map(Some)
+                    .ok_or_else(|| {
+                        trace!("unsupported grpc-encoding; encoding={}", name);
+                        Status::UNIMPLEMENTED
+                            .with_message(format!("unsupported grpc-encoding: {}", name))
+                    })
+            }
+            Err(_) => Err(Status::UNIMPLEMENTED.with_message("invalid grpc-encoding")),
+        }
+    }
+
+    /// Get the compressor for responding to a request, if the peer's
+    /// `grpc-accept-encoding` includes the algorithm chosen for sending.
+    pub(crate) fn response_compressor(&self, headers: &HeaderMap) -> Option<Arc<dyn Compressor>> {
+        let send = self.send?;
+
+        let accepted = headers.get_all(ACCEPT_ENCODING_HEADER)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|name| name.trim() == send);
+
+        if accepted {
+            self.get(send)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::new()
+    }
+}
+
+// ===== impl UnregisteredAlgorithm =====
+
+impl UnregisteredAlgorithm {
+    /// The name of the algorithm that is not registered.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl fmt::Display for UnregisteredAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unregistered compression algorithm: {}", self.name)
+    }
+}
+
+impl Error for UnregisteredAlgorithm {
+    fn description(&self) -> &str {
+        "unregistered compression algorithm"
+    }
+}
+
+// ===== impl MessageCompression =====
+
+impl MessageCompression {
+    /// Returns a new handle with compression enabled.
+    pub fn new() -> Self {
+        MessageCompression {
+            enabled: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    /// Enable or disable compression of subsequently sent messages.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::SeqCst);
+    }
+
+    /// Returns true if sent messages are compressed.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::SeqCst)
+    }
+}
+
+impl Default for MessageCompression {
+    fn default() -> Self {
+        MessageCompression::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Code;
+    use test_util::Reverse;
+
+    /// A compressible message.
+    fn message() -> Vec<u8> {
+        (0..4096).map(|i| (i % 7) as u8).collect()
+    }
+
+    fn round_trip<C: Compressor>(compressor: C) {
+        let message = message();
+
+        let mut compressed = Vec::new();
+        compressor.compress(&message, &mut compressed).expect("compress");
+        assert!(compressed.len() < message.len());
+
+        let mut decompressed = Vec::new();
+        compressor.decompress(&compressed, &mut decompressed, message.len())
+            .expect("decompress");
+        assert_eq!(decompressed, message);
+    }
+
+    fn headers(name: &'static str, value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_static(value));
+        headers
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn gzip_round_trip() {
+        round_trip(Gzip);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn gzip_decompress_stops_at_limit() {
+        let bomb = vec![0; 1024 * 1024];
+
+        let mut compressed = Vec::new();
+        Gzip.compress(&bomb, &mut compressed).expect("compress");
+
+        let mut decompressed = Vec::new();
+        Gzip.decompress(&compressed, &mut decompressed, 1025).expect("decompress");
+        assert_eq!(decompressed.len(), 1025);
+    }
+
+    #[cfg(feature = "deflate")]
+    #[test]
+    fn deflate_round_trip() {
+        round_trip(Deflate);
+    }
+
+    #[test]
+    fn send_requires_a_registered_algorithm() {
+        let mut compression = Compression::none();
+
+        let err = compression.send(Some("reverse")).expect_err("unregistered");
+        assert_eq!(err.name(), "reverse");
+        assert!(compression.send_compressor().is_none());
+
+        compression.register(Reverse);
+        compression.send(Some("reverse")).expect("registered");
+        assert_eq!(compression.send_compressor().map(|c| c.name()), Some("reverse"));
+
+        compression.send(None).expect("no compression");
+        assert!(compression.send_compressor().is_none());
+    }
+
+    #[test]
+    fn decompressor_for_grpc_encoding() {
+        let mut compression = Compression::none();
+        compression.register(Reverse);
+
+        assert!(compression.decompressor(&HeaderMap::new()).unwrap().is_none());
+        assert!(compression.decompressor(&headers(ENCODING_HEADER, IDENTITY)).unwrap().is_none());
+
+        let decompressor = compression.decompressor(&headers(ENCODING_HEADER, "reverse"));
+        assert_eq!(decompressor.unwrap().map(|c| c.name()), Some("reverse"));
+
+        let err = compression.decompressor(&headers(ENCODING_HEADER, "snappy"))
+            .expect_err("unsupported encoding");
+        assert_eq!(err.code(), Code::UNIMPLEMENTED);
+    }
+
+    #[test]
+    fn response_compressor_requires_the_peer_to_accept_it() {
+        let mut compression = Compression::none();
+        compression.register(Reverse);
+        assert!(compression.response_compressor(&headers(ACCEPT_ENCODING_HEADER, "reverse")).is_none());
+
+        compression.send(Some("reverse")).unwrap();
+
+        let accepted = headers(ACCEPT_ENCODING_HEADER, "gzip, reverse");
+        assert_eq!(compression.response_compressor(&accepted).map(|c| c.name()), Some("reverse"));
+
+        let refused = headers(ACCEPT_ENCODING_HEADER, "gzip");
+        assert!(compression.response_compressor(&refused).is_none());
+    }
+
+    #[test]
+    fn accept_encoding_lists_registered_algorithms() {
+        let mut compression = Compression::none();
+        assert!(compression.accept_encoding().is_none());
+
+        compression.register(Reverse);
+        assert_eq!(compression.accept_encoding().unwrap(), "reverse");
+    }
+}
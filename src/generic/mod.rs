@@ -1,8 +1,11 @@
 //! gRPC generic over encoder / decoder.
 
+pub mod client;
+pub mod compression;
 pub mod server;
 
 mod codec;
+mod raw;
 
 pub use self::codec::{
     Codec,
@@ -10,6 +13,10 @@ pub use self::codec::{
     Decoder,
     Streaming,
     Encode,
+    EncodeOptions,
     EncodeBuf,
     DecodeBuf,
 };
+pub use self::raw::{BytesCodec, BytesEncoder, BytesDecoder};
+
+pub(crate) use self::codec::DEFAULT_MAX_DECODING_MESSAGE_SIZE;
@@ -0,0 +1,66 @@
+use generic::{Codec, Encoder, Decoder, EncodeBuf, DecodeBuf};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+/// Codec passing messages through as raw bytes, without parsing them.
+///
+/// For proxies, which forward messages they cannot decode.
+#[derive(Debug, Clone, Default)]
+pub struct BytesCodec;
+
+#[derive(Debug, Clone, Default)]
+pub struct BytesEncoder;
+
+#[derive(Debug, Clone, Default)]
+pub struct BytesDecoder;
+
+// ===== impl BytesCodec =====
+
+impl BytesCodec {
+    /// Create a new bytes codec
+    pub fn new() -> Self {
+        BytesCodec
+    }
+}
+
+impl Codec for BytesCodec {
+    /// Plain gRPC, as the encoding of the messages is not known.
+    const CONTENT_TYPE: &'static str = "application/grpc";
+
+    type Encode = Bytes;
+    type Encoder = BytesEncoder;
+    type Decode = Bytes;
+    type Decoder = BytesDecoder;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        BytesEncoder
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        BytesDecoder
+    }
+}
+
+// ===== impl BytesEncoder =====
+
+impl Encoder for BytesEncoder {
+    type Item = Bytes;
+
+    fn encode(&mut self, item: Bytes, buf: &mut EncodeBuf) -> Result<(), ::Error> {
+        buf.reserve(item.len());
+        buf.put_slice(&item);
+        Ok(())
+    }
+}
+
+// ===== impl BytesDecoder =====
+
+impl Decoder for BytesDecoder {
+    type Item = Bytes;
+
+    fn decode(&mut self, buf: &mut DecodeBuf) -> Result<Bytes, ::Error> {
+        let mut bytes = BytesMut::with_capacity(buf.remaining());
+        bytes.put(buf);
+        Ok(bytes.freeze())
+    }
+}
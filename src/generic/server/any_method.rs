@@ -0,0 +1,187 @@
+use super::{streaming, Grpc, StreamingService};
+use generic::{Codec, Encode, Streaming};
+
+use futures::{future, Async, Poll};
+use {h2, http};
+use tower::{NewService, Service};
+use tower_h2::RecvBody;
+
+/// Serves every method path with a single streaming service.
+///
+/// For proxies and other servers that do not know their methods in advance.
+/// The service can find the method called with `Request::method_path`:
+///
+/// ```ignore
+/// let proxy = AnyMethod::new(Forward::new(upstream), Grpc::new(BytesCodec::new()));
+/// let h2 = tower_h2::Server::new(proxy, Default::default(), executor);
+/// ```
+#[derive(Debug, Clone)]
+pub struct AnyMethod<T, C> {
+    inner: T,
+    grpc: Grpc<C>,
+}
+
+// ===== impl AnyMethod =====
+
+impl<T, C> AnyMethod<T, C>
+where T: StreamingService<Request = C::Decode,
+                    RequestStream = Streaming<C::Decoder>,
+                         Response = C::Encode>,
+      C: Codec,
+{
+    /// Serve every method with `inner`, decoding and encoding messages as
+    /// configured by `grpc`.
+    pub fn new(inner: T, grpc: Grpc<C>) -> Self {
+        AnyMethod { inner, grpc }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T, C> Service for AnyMethod<T, C>
+where T: StreamingService<Request = C::Decode,
+                    RequestStream = Streaming<C::Decoder>,
+                         Response = C::Encode>,
+      C: Codec,
+{
+    type Request = http::Request<RecvBody>;
+    type Response = http::Response<Encode<C::Encoder, T::ResponseStream>>;
+    type Error = h2::Error;
+    type Future = streaming::ResponseFuture<T::Future, C::Encoder>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(Async::Ready(()))
+    }
+
+    fn call(&mut self, request: Self::Request) -> Self::Future {
+        trace!("serving gRPC method; path={}", request.uri().path());
+        self.grpc.streaming(&mut self.inner, request)
+    }
+}
+
+impl<T, C> NewService for AnyMethod<T, C>
+where T: StreamingService<Request = C::Decode,
+                    RequestStream = Streaming<C::Decoder>,
+                         Response = C::Encode> + Clone,
+      C: Codec + Clone,
+{
+    type Request = http::Request<RecvBody>;
+    type Response = http::Response<Encode<C::Encoder, T::ResponseStream>>;
+    type Error = h2::Error;
+    type Service = Self;
+    type InitError = h2::Error;
+    type Future = future::FutureResult<Self, Self::InitError>;
+
+    fn new_service(&self) -> Self::Future {
+        future::ok(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Request, Response};
+    use generic::{BytesCodec, BytesDecoder};
+    use test_util::{frame, TestBody};
+
+    use bytes::Bytes;
+    use futures::Future;
+    use http::header;
+    use tower_h2::Body;
+    use tower_ready_service::ReadyService;
+
+    use std::marker::PhantomData;
+    use std::sync::{Arc, Mutex};
+
+    /// Streams the request messages back, keeping the method path called.
+    #[derive(Debug)]
+    struct Forward<B> {
+        path: Arc<Mutex<Option<String>>>,
+        _body: PhantomData<B>,
+    }
+
+    impl<B> Forward<B> {
+        fn new() -> Self {
+            Forward {
+                path: Arc::new(Mutex::new(None)),
+                _body: PhantomData,
+            }
+        }
+    }
+
+    impl<B> ReadyService for Forward<B>
+    where B: Body,
+          B::Data: Into<Bytes>,
+    {
+        type Request = Request<Streaming<BytesDecoder, B>>;
+        type Response = Response<Streaming<BytesDecoder, B>>;
+        type Error = ::Error;
+        type Future = future::FutureResult<Self::Response, ::Error>;
+
+        fn call(&mut self, request: Self::Request) -> Self::Future {
+            *self.path.lock().unwrap() = request.method_path().map(|path| path.to_string());
+            future::ok(Response::new(request.into_inner()))
+        }
+    }
+
+    fn request<B>(path: &str, body: B) -> http::Request<B> {
+        http::Request::post(path)
+            .header(header::CONTENT_TYPE, "application/grpc")
+            .header(header::TE, "trailers")
+            .body(body)
+            .unwrap()
+    }
+
+    #[test]
+    fn payloads_are_forwarded_unchanged() {
+        let payloads: [&[u8]; 3] = [b"\x00\xffnot protobuf", b"", b"\x0a\x05hello"];
+        let body = TestBody::new(payloads.iter().map(|payload| frame(payload)));
+
+        let mut forward = Forward::new();
+        let path = forward.path.clone();
+
+        let mut grpc = Grpc::new(BytesCodec::new());
+        let response = grpc.streaming(&mut forward, request("/any.Service/Anything", body))
+            .wait()
+            .expect("response");
+        let mut body = response.into_body();
+
+        // The request stream is polled within a task.
+        let sent = future::lazy(move || {
+            let mut sent = Vec::new();
+            while let Async::Ready(Some(data)) = body.poll_data().expect("poll_data") {
+                sent.extend_from_slice(&data);
+            }
+            Ok::<_, ()>(sent)
+        }).wait().unwrap();
+
+        let expected = payloads.iter()
+            .flat_map(|payload| frame(payload))
+            .collect::<Vec<_>>();
+        assert_eq!(sent, expected);
+        assert_eq!(*path.lock().unwrap(), Some("/any.Service/Anything".to_string()));
+    }
+
+    #[test]
+    fn any_method_sets_the_method_path() {
+        let forward = Forward::new();
+        let path = forward.path.clone();
+
+        let mut any = AnyMethod::new(forward, Grpc::new(BytesCodec::new()));
+
+        for &method in &["/a.A/One", "/b.B/Two"] {
+            Service::call(&mut any, request(method, RecvBody::default()))
+                .wait()
+                .expect("response");
+            assert_eq!(path.lock().unwrap().as_ref().map(|p| &p[..]), Some(method));
+        }
+    }
+}
@@ -1,10 +1,13 @@
-use {Response};
+use {Response, Status};
 use super::streaming;
 use super::unary::Once;
-use generic::{Encoder, Encode};
+use generic::{Encoder, Encode, EncodeOptions};
 
 use {h2, http};
 use futures::{Future, Poll};
+use http::StatusCode;
+
+use std::time::Instant;
 
 #[derive(Debug)]
 pub struct ResponseFuture<T, E> {
@@ -22,9 +25,16 @@ impl<T, E> ResponseFuture<T, E>
 where T: Future<Item = Response<E::Item>, Error = ::Error>,
       E: Encoder,
 {
-    pub fn new(inner: T, encoder: E) -> Self {
+    pub fn new(inner: T, encoder: E, options: EncodeOptions, deadline: Option<Instant>) -> Self {
         let inner = Inner { inner };
-        let inner = streaming::ResponseFuture::new(inner, encoder);
+        let inner = streaming::ResponseFuture::new(inner, encoder, options, deadline);
+        ResponseFuture { inner }
+    }
+
+    /// Create a response future answering with `http_status`, without
+    /// calling the service.
+    pub(crate) fn rejected(http_status: StatusCode, status: Status, options: EncodeOptions) -> Self {
+        let inner = streaming::ResponseFuture::rejected(http_status, status, options);
         ResponseFuture { inner }
     }
 }
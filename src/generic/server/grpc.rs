@@ -1,14 +1,24 @@
-use ::Request;
+use {CancelHandle, Request, Status};
 use super::{streaming, server_streaming, client_streaming, unary};
-use generic::{Codec, Streaming};
+use generic::{Codec, EncodeOptions, Streaming, DEFAULT_MAX_DECODING_MESSAGE_SIZE};
+use generic::compression::{Compression, IDENTITY};
 use generic::server::{StreamingService, ServerStreamingService, ClientStreamingService, UnaryService};
+use web;
 
+use bytes::Bytes;
 use http;
-use tower_h2::{Body, Data};
+use http::StatusCode;
+use http::header::{self, HeaderValue};
+use tower_h2::Body;
+
+const GRPC_CONTENT_TYPE: &str = "application/grpc";
 
 #[derive(Debug, Clone)]
 pub struct Grpc<T> {
     codec: T,
+    compression: Compression,
+    max_decoding_message_size: Option<usize>,
+    max_encoding_message_size: Option<usize>,
 }
 
 // ===== impl Grpc =====
@@ -17,7 +27,35 @@ impl<T> Grpc<T>
 where T: Codec,
 {
     pub fn new(codec: T) -> Self {
-        Grpc { codec }
+        Grpc {
+            codec,
+            compression: Compression::new(),
+            max_decoding_message_size: Some(DEFAULT_MAX_DECODING_MESSAGE_SIZE),
+            max_encoding_message_size: None,
+        }
+    }
+
+    /// Set the compression algorithms used for requests and responses.
+    pub fn set_compression(&mut self, compression: Compression) -> &mut Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Limit the size of request messages, in bytes.
+    ///
+    /// Larger requests fail with `RESOURCE_EXHAUSTED` before being buffered.
+    /// Defaults to 4 MiB; `None` removes the limit.
+    pub fn set_max_decoding_message_size(&mut self, limit: Option<usize>) -> &mut Self {
+        self.max_decoding_message_size = limit;
+        self
+    }
+
+    /// Limit the size of response messages, in bytes.
+    ///
+    /// Larger responses fail with `RESOURCE_EXHAUSTED`. Unlimited by default.
+    pub fn set_max_encoding_message_size(&mut self, limit: Option<usize>) -> &mut Self {
+        self.max_encoding_message_size = limit;
+        self
     }
 
     pub fn unary<S, B>(&mut self,
@@ -26,10 +64,18 @@ where T: Codec,
         -> unary::ResponseFuture<S, T::Encoder, Streaming<T::Decoder, B>>
     where S: UnaryService<Request = T::Decode,
                          Response = T::Encode>,
-          B: Body<Data = Data>,
+          B: Body,
+          B::Data: Into<Bytes>,
     {
+        let mut options = self.encode_options(&request);
+
+        if let Err((http_status, status)) = self.check_request(&request) {
+            return unary::ResponseFuture::rejected(http_status, status, options);
+        }
+
         let request = self.map_request(request);
-        unary::ResponseFuture::new(service, request, self.codec.encoder())
+        options.cancel = request.cancel();
+        unary::ResponseFuture::new(service, request, self.codec.encoder(), options)
     }
 
     pub fn client_streaming<S, B>(&mut self,
@@ -39,10 +85,20 @@ where T: Codec,
     where S: ClientStreamingService<Request = T::Decode,
                               RequestStream = Streaming<T::Decoder, B>,
                                    Response = T::Encode>,
-          B: Body<Data = Data>,
+          B: Body,
+          B::Data: Into<Bytes>,
     {
-        let response = service.call(self.map_request(request));
-        client_streaming::ResponseFuture::new(response, self.codec.encoder())
+        let mut options = self.encode_options(&request);
+
+        if let Err((http_status, status)) = self.check_request(&request) {
+            return client_streaming::ResponseFuture::rejected(http_status, status, options);
+        }
+
+        let request = self.map_request(request);
+        options.cancel = request.cancel();
+        let deadline = request.deadline();
+        let response = service.call(request);
+        client_streaming::ResponseFuture::new(response, self.codec.encoder(), options, deadline)
     }
 
     pub fn server_streaming<S, B>(&mut self,
@@ -51,10 +107,18 @@ where T: Codec,
         -> server_streaming::ResponseFuture<S, T::Encoder, Streaming<T::Decoder, B>>
     where S: ServerStreamingService<Request = T::Decode,
                                    Response = T::Encode>,
-          B: Body<Data = Data>,
+          B: Body,
+          B::Data: Into<Bytes>,
     {
+        let mut options = self.encode_options(&request);
+
+        if let Err((http_status, status)) = self.check_request(&request) {
+            return server_streaming::ResponseFuture::rejected(http_status, status, options);
+        }
+
         let request = self.map_request(request);
-        server_streaming::ResponseFuture::new(service, request, self.codec.encoder())
+        options.cancel = request.cancel();
+        server_streaming::ResponseFuture::new(service, request, self.codec.encoder(), options)
     }
 
     pub fn streaming<S, B>(&mut self,
@@ -64,22 +128,146 @@ where T: Codec,
     where S: StreamingService<Request = T::Decode,
                         RequestStream = Streaming<T::Decoder, B>,
                              Response = T::Encode>,
-          B: Body<Data = Data>,
+          B: Body,
+          B::Data: Into<Bytes>,
     {
-        let response = service.call(self.map_request(request));
-        streaming::ResponseFuture::new(response, self.codec.encoder())
+        let mut options = self.encode_options(&request);
+
+        if let Err((http_status, status)) = self.check_request(&request) {
+            return streaming::ResponseFuture::rejected(http_status, status, options);
+        }
+
+        let request = self.map_request(request);
+        options.cancel = request.cancel();
+        let deadline = request.deadline();
+        let response = service.call(request);
+        streaming::ResponseFuture::new(response, self.codec.encoder(), options, deadline)
+    }
+
+    /// Options for encoding the response to `request`.
+    fn encode_options<B>(&self, request: &http::Request<B>) -> EncodeOptions {
+        let mut options = EncodeOptions::default();
+        options.compressor = self.compression.response_compressor(request.headers());
+        options.max_message_size = self.max_encoding_message_size;
+        options.content_type = Some(self.response_content_type(request));
+
+        // Advertised on every response, so a client whose `grpc-encoding`
+        // is rejected with `UNIMPLEMENTED` learns what to use instead.
+        options.accept_encoding = Some(self.compression.accept_encoding()
+            .unwrap_or_else(|| HeaderValue::from_static(IDENTITY)));
+
+        options
+    }
+
+    /// Check that `request` is a gRPC request the codec can decode.
+    ///
+    /// Only validates the request: the codec is fixed, so a content type
+    /// whose `+suffix` names a different message encoding is rejected rather
+    /// than served with another codec. A `Router` can send each encoding to
+    /// a service built on the matching codec.
+    ///
+    /// Otherwise, returns the HTTP status and gRPC status to reject it with.
+    /// The gRPC status is the one a client infers from the HTTP status, so
+    /// clients reading either agree: `UNKNOWN` for a non-POST method (405) or
+    /// a content type the codec does not handle (415), and `INTERNAL` for a
+    /// missing `te: trailers` header (400).
+    fn check_request<B>(&self, request: &http::Request<B>) -> Result<(), (StatusCode, Status)> {
+        if request.method() != http::Method::POST {
+            debug!("unsupported gRPC request method; method={}", request.method());
+            return reject(
+                StatusCode::METHOD_NOT_ALLOWED,
+                format!("unsupported HTTP method: {}", request.method()));
+        }
+
+        let content_type = request.headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("");
+
+        let supported = match (encoding(content_type), encoding(T::CONTENT_TYPE)) {
+            // The codec does not parse the messages.
+            (Some(_), Some("")) => true,
+            // Messages are protobuf unless the content type says otherwise.
+            (Some(""), Some(codec)) => codec == "proto",
+            (Some(request), Some(codec)) => request == codec,
+            _ => false,
+        };
+
+        if !supported {
+            debug!("unsupported gRPC content type; content-type={:?}", content_type);
+            return reject(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                format!("unsupported content type: {:?}", content_type));
+        }
+
+        // Requests translated by `web::GrpcWeb` do not expect trailers.
+        let web = request.extensions().get::<web::Mode>().is_some();
+        let trailers = request.headers()
+            .get(header::TE)
+            .map(|te| te == "trailers")
+            .unwrap_or(false);
+
+        if !web && !trailers {
+            debug!("gRPC request without `te: trailers`");
+            return reject(
+                StatusCode::BAD_REQUEST,
+                "missing `te: trailers` header, possibly removed by a proxy".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// The content type of the response to `request`.
+    ///
+    /// A codec that does not parse messages answers with the content type
+    /// of the request.
+    fn response_content_type<B>(&self, request: &http::Request<B>) -> HeaderValue {
+        if encoding(T::CONTENT_TYPE) == Some("") {
+            let content_type = request.headers().get(header::CONTENT_TYPE);
+
+            if let Some(content_type) = content_type {
+                let valid = content_type.to_str().ok().and_then(encoding).is_some();
+
+                if valid {
+                    return content_type.clone();
+                }
+            }
+        }
+
+        HeaderValue::from_static(T::CONTENT_TYPE)
     }
 
     /// Map an inbound HTTP request to a streaming decoded request
     fn map_request<B>(&mut self, request: http::Request<B>)
         -> Request<Streaming<T::Decoder, B>>
-    where B: Body<Data = Data>,
+    where B: Body,
+          B::Data: Into<Bytes>,
     {
         // Map the request body
-        let (head, body) = request.into_parts();
+        let (mut head, body) = request.into_parts();
+
+        // Canceled when the client resets the stream, and exposed to the
+        // service through `Request::canceled`.
+        let cancel = CancelHandle::new();
+        head.extensions.insert(cancel.clone());
 
         // Wrap the body stream with a decoder
-        let body = Streaming::new(self.codec.decoder(), body, false);
+        let body = Streaming::new(self.codec.decoder(), body, false)
+            .with_max_message_size(self.max_decoding_message_size)
+            .with_cancel(Some(cancel));
+
+        // Decompress the messages, or fail the stream if the encoding is
+        // not supported.
+        let body = match self.compression.decompressor(&head.headers) {
+            Ok(decompressor) => body.with_decompressor(decompressor),
+            Err(status) => body.with_error(status),
+        };
+
+        // Requests translated by `web::GrpcWeb` may be base64 encoded.
+        let body = match head.extensions.get::<web::Mode>() {
+            Some(&mode) => body.with_web_mode(mode),
+            None => body,
+        };
 
         // Reconstruct the HTTP request
         let request = http::Request::from_parts(head, body);
@@ -88,3 +276,225 @@ where T: Codec,
         Request::from_http(request)
     }
 }
+
+// ===== utility fns =====
+
+/// Reject a request with `http_status`, and the gRPC status a client would
+/// infer from it.
+fn reject(http_status: StatusCode, message: String) -> Result<(), (StatusCode, Status)> {
+    Err((http_status, Status::from_http_status(http_status).with_message(message)))
+}
+
+/// Returns the message encoding of a gRPC content type, such as `proto` for
+/// `application/grpc+proto`, or `None` if it is not a gRPC content type.
+pub(crate) fn encoding(content_type: &str) -> Option<&str> {
+    let content_type = content_type.split(';').next().unwrap_or("").trim();
+
+    if !content_type.starts_with(GRPC_CONTENT_TYPE) {
+        return None;
+    }
+
+    match &content_type[GRPC_CONTENT_TYPE.len()..] {
+        "" => Some(""),
+        subtype if subtype.starts_with('+') => Some(&subtype[1..]),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Code, Response};
+    use generic::BytesCodec;
+    use test_util::{frame, TestBody};
+
+    use futures::{future, Async, Future};
+    use tower_ready_service::ReadyService;
+
+    use std::sync::{Arc, Mutex};
+
+    /// Answers with the request message.
+    #[derive(Debug)]
+    struct Echo;
+
+    /// Never answers, keeping the request's cancellation future.
+    #[derive(Debug, Default)]
+    struct Pending(Arc<Mutex<Option<::Canceled>>>);
+
+    impl ReadyService for Echo {
+        type Request = Request<Bytes>;
+        type Response = Response<Bytes>;
+        type Error = ::Error;
+        type Future = future::FutureResult<Self::Response, ::Error>;
+
+        fn call(&mut self, request: Self::Request) -> Self::Future {
+            future::ok(Response::new(request.into_inner()))
+        }
+    }
+
+    impl ReadyService for Pending {
+        type Request = Request<Bytes>;
+        type Response = Response<Bytes>;
+        type Error = ::Error;
+        type Future = future::Empty<Self::Response, ::Error>;
+
+        fn call(&mut self, request: Self::Request) -> Self::Future {
+            *self.0.lock().unwrap() = Some(request.canceled());
+            future::empty()
+        }
+    }
+
+    /// A gRPC request with the given messages.
+    fn request(messages: &[&[u8]]) -> http::Request<TestBody> {
+        let body = TestBody::new(messages.iter().map(|message| frame(message)));
+
+        http::Request::post("/pkg.Service/Method")
+            .header(header::CONTENT_TYPE, "application/grpc+proto")
+            .header(header::TE, "trailers")
+            .body(body)
+            .unwrap()
+    }
+
+    /// Call `Echo` with `request`, returning the HTTP status, response
+    /// frames and gRPC status.
+    fn unary(request: http::Request<TestBody>) -> (StatusCode, Vec<Bytes>, Status) {
+        let mut grpc = Grpc::new(BytesCodec::new());
+        let response = grpc.unary(Echo, request)
+            .wait()
+            .expect("response");
+        let (head, mut body) = response.into_parts();
+
+        let mut frames = Vec::new();
+        while let Async::Ready(Some(frame)) = body.poll_data().expect("poll_data") {
+            frames.push(frame);
+        }
+
+        let status = match body.poll_trailers().expect("poll_trailers") {
+            Async::Ready(Some(trailers)) => {
+                Status::from_header_map(&trailers).expect("trailers missing grpc-status")
+            }
+            trailers => panic!("unexpected trailers: {:?}", trailers),
+        };
+
+        (head.status, frames, status)
+    }
+
+    #[test]
+    fn unary_with_one_message() {
+        let (http_status, frames, status) = unary(request(&[b"hello"]));
+
+        assert_eq!(http_status, StatusCode::OK);
+        assert_eq!(frames, vec![frame(b"hello")]);
+        assert_eq!(status.code(), Code::OK);
+    }
+
+    #[test]
+    fn unary_without_a_message_is_internal() {
+        let (_, frames, status) = unary(request(&[]));
+
+        assert!(frames.is_empty());
+        assert_eq!(status.code(), Code::INTERNAL);
+        assert_eq!(status.message(), "expected one request message, received none");
+    }
+
+    #[test]
+    fn unary_with_extra_messages_is_internal() {
+        let (_, frames, status) = unary(request(&[b"hello", b"again"]));
+
+        assert!(frames.is_empty());
+        assert_eq!(status.code(), Code::INTERNAL);
+        assert_eq!(status.message(), "expected one request message, received more");
+    }
+
+    #[test]
+    fn non_post_request_is_rejected() {
+        let mut request = request(&[b"hello"]);
+        *request.method_mut() = http::Method::GET;
+
+        let (http_status, frames, status) = unary(request);
+
+        assert_eq!(http_status, StatusCode::METHOD_NOT_ALLOWED);
+        assert!(frames.is_empty());
+        assert_eq!(status.code(), Status::from_http_status(http_status).code());
+        assert_eq!(status.code(), Code::UNKNOWN);
+    }
+
+    #[test]
+    fn non_grpc_content_type_is_rejected() {
+        let mut request = request(&[b"hello"]);
+        request.headers_mut()
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+
+        let (http_status, frames, status) = unary(request);
+
+        assert_eq!(http_status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert!(frames.is_empty());
+        assert_eq!(status.code(), Status::from_http_status(http_status).code());
+        assert_eq!(status.code(), Code::UNKNOWN);
+    }
+
+    #[test]
+    fn missing_te_trailers_is_rejected() {
+        let mut request = request(&[b"hello"]);
+        request.headers_mut().remove(header::TE);
+
+        let (http_status, frames, status) = unary(request);
+
+        assert_eq!(http_status, StatusCode::BAD_REQUEST);
+        assert!(frames.is_empty());
+        assert_eq!(status.code(), Code::INTERNAL);
+    }
+
+    #[test]
+    fn dropped_response_cancels_the_call() {
+        let service = Pending::default();
+        let canceled = service.0.clone();
+
+        let mut grpc = Grpc::new(BytesCodec::new());
+        let response = grpc.unary(service, request(&[b"hello"]));
+
+        // Poll once so the service is called.
+        let response = future::lazy(move || {
+            let mut response = response;
+            assert!(response.poll().expect("poll").is_not_ready());
+            Ok::<_, ()>(response)
+        }).wait().unwrap();
+
+        let canceled = canceled.lock().unwrap().take().expect("service called");
+        drop(response);
+
+        assert_eq!(canceled.wait(), Ok(()));
+    }
+
+    #[test]
+    fn unsupported_encoding_is_unimplemented() {
+        let mut request = request(&[b"hello"]);
+        request.headers_mut().insert("grpc-encoding", HeaderValue::from_static("snappy"));
+
+        let mut grpc = Grpc::new(BytesCodec::new());
+        let response = grpc.unary(Echo, request)
+            .wait()
+            .expect("response");
+
+        // The client learns which encodings it can use instead.
+        assert!(response.headers().contains_key("grpc-accept-encoding"));
+
+        let mut body = response.into_body();
+        assert_eq!(body.poll_data().expect("poll_data"), Async::Ready(None));
+
+        let status = match body.poll_trailers().expect("poll_trailers") {
+            Async::Ready(Some(trailers)) => Status::from_header_map(&trailers).unwrap(),
+            trailers => panic!("unexpected trailers: {:?}", trailers),
+        };
+        assert_eq!(status.code(), Code::UNIMPLEMENTED);
+    }
+
+    #[test]
+    fn content_type_encoding() {
+        assert_eq!(encoding("application/grpc"), Some(""));
+        assert_eq!(encoding("application/grpc+proto"), Some("proto"));
+        assert_eq!(encoding("application/grpc+json; charset=utf-8"), Some("json"));
+        assert_eq!(encoding("application/grpcx"), None);
+        assert_eq!(encoding("application/json"), None);
+    }
+}
@@ -1,3 +1,4 @@
+mod any_method;
 mod grpc;
 
 pub mod client_streaming;
@@ -5,8 +6,11 @@ pub mod server_streaming;
 pub mod streaming;
 pub mod unary;
 
+pub use self::any_method::AnyMethod;
 pub use self::grpc::Grpc;
 
+pub(crate) use self::grpc::encoding;
+
 use {Request, Response};
 
 use futures::{Future, Stream};
@@ -1,10 +1,11 @@
-use {Request, Response};
+use {Request, Response, Status};
 use super::streaming;
-use generic::{Encoder, Encode};
+use generic::{Encoder, Encode, EncodeOptions};
 use generic::server::ServerStreamingService;
 
 use {h2, http};
 use futures::{Future, Stream, Poll};
+use http::{HeaderMap, StatusCode};
 
 use std::fmt;
 
@@ -19,13 +20,14 @@ struct Inner<T, S>
 where T: ServerStreamingService,
 {
     inner: T,
-    state: Option<State<T::Future, S>>,
+    state: Option<State<T::Future, S, T::Request>>,
 }
 
 #[derive(Debug)]
-enum State<T, S> {
-    /// Waiting for the request to be received
-    Requesting(Request<S>),
+enum State<T, S, M> {
+    /// Waiting for the request to be received, holding its message once
+    /// read.
+    Requesting(Request<S>, Option<M>),
 
     /// Waiting for the response future to resolve
     Responding(T),
@@ -38,13 +40,21 @@ where T: ServerStreamingService<Request = S::Item, Response = E::Item>,
       E: Encoder,
       S: Stream<Error = ::Error>,
 {
-    pub fn new(inner: T, request: Request<S>, encoder: E) -> Self {
+    pub fn new(inner: T, request: Request<S>, encoder: E, options: EncodeOptions) -> Self {
+        let deadline = request.deadline();
         let inner = Inner {
             inner,
-            state: Some(State::Requesting(request)),
+            state: Some(State::Requesting(request, None)),
         };
 
-        let inner = streaming::ResponseFuture::new(inner, encoder);
+        let inner = streaming::ResponseFuture::new(inner, encoder, options, deadline);
+        ResponseFuture { inner }
+    }
+
+    /// Create a response future answering with `http_status`, without
+    /// calling the service.
+    pub(crate) fn rejected(http_status: StatusCode, status: Status, options: EncodeOptions) -> Self {
+        let inner = streaming::ResponseFuture::rejected(http_status, status, options);
         ResponseFuture { inner }
     }
 }
@@ -76,8 +86,17 @@ where T: ServerStreamingService<Request = S::Item>,
 
         loop {
             let msg = match *self.state.as_mut().unwrap() {
-                Requesting(ref mut request) => {
-                    try_ready!(request.get_mut().poll())
+                Requesting(ref mut request, ref message) => {
+                    let msg = try_ready!(request.get_mut().poll());
+
+                    // The request must hold exactly one message, so the
+                    // service is called only once the stream has ended.
+                    if msg.is_some() && message.is_some() {
+                        debug!("request with more than one message");
+                        return Err(internal("expected one request message, received more"));
+                    }
+
+                    msg
                 }
                 Responding(ref mut fut) => {
                     return fut.poll();
@@ -86,20 +105,26 @@ where T: ServerStreamingService<Request = S::Item>,
 
             match msg {
                 Some(msg) => {
+                    match *self.state.as_mut().unwrap() {
+                        Requesting(_, ref mut message) => *message = Some(msg),
+                        _ => unreachable!(),
+                    }
+                }
+                None => {
                     match self.state.take().unwrap() {
-                        Requesting(request) => {
+                        Requesting(request, Some(msg)) => {
                             let request = request.map(|_| msg);
                             let response = self.inner.call(request);
 
                             self.state = Some(Responding(response));
                         }
+                        Requesting(_, None) => {
+                            debug!("request without a message");
+                            return Err(internal("expected one request message, received none"));
+                        }
                         _ => unreachable!(),
                     }
                 }
-                None => {
-                    // TODO: Do something
-                    return Err(::Error::Inner(()));
-                }
             }
         }
     }
@@ -136,3 +161,9 @@ where T: ServerStreamingService + fmt::Debug,
             .finish()
     }
 }
+
+// ===== utility fns =====
+
+fn internal(message: &str) -> ::Error {
+    ::Error::Grpc(Status::INTERNAL.with_message(message), HeaderMap::new())
+}
@@ -1,13 +1,24 @@
-use {Response};
-use generic::{Encoder, Encode};
+use {Response, Status};
+use deadline::Timer;
+use generic::{Encoder, Encode, EncodeOptions};
+use generic::compression::{MessageCompression, ACCEPT_ENCODING_HEADER, ENCODING_HEADER};
+use metadata::TrailingMetadata;
 
 use {http, h2};
 use futures::{Future, Stream, Poll, Async};
+use http::StatusCode;
+use http::header::{self, HeaderValue};
+
+use std::time::Instant;
 
 #[derive(Debug)]
 pub struct ResponseFuture<T, E> {
-    inner: T,
+    /// The response future, or the HTTP status and gRPC status rejecting
+    /// the request.
+    inner: Result<T, (StatusCode, Status)>,
     encoder: Option<E>,
+    options: EncodeOptions,
+    deadline: Timer,
 }
 
 // ===== impl ResponseFuture =====
@@ -16,12 +27,43 @@ impl<T, E, S> ResponseFuture<T, E>
 where T: Future<Item = Response<S>,
                Error = ::Error>,
       E: Encoder,
-      S: Stream<Item = E::Item>,
+      S: Stream<Item = E::Item, Error = ::Error>,
 {
-    pub fn new(inner: T, encoder: E) -> Self {
+    pub fn new(inner: T, encoder: E, options: EncodeOptions, deadline: Option<Instant>) -> Self {
         ResponseFuture {
-            inner,
+            inner: Ok(inner),
             encoder: Some(encoder),
+            options,
+            deadline: Timer::new(deadline),
+        }
+    }
+
+    /// Create a response future answering with `http_status`, without
+    /// calling the service.
+    pub(crate) fn rejected(http_status: StatusCode, status: Status, options: EncodeOptions) -> Self {
+        ResponseFuture {
+            inner: Err((http_status, status)),
+            encoder: None,
+            options,
+            deadline: Timer::default(),
+        }
+    }
+
+    /// A response carrying only `status`, in the trailers.
+    fn error_response(&self, status: Status) -> http::Response<Encode<E, S>> {
+        let mut response = Response::new(Encode::error(status)).into_http();
+        self.set_headers(&mut response);
+        response
+    }
+
+    /// Set the headers every response carries.
+    fn set_headers<B>(&self, response: &mut http::Response<B>) {
+        if let Some(ref content_type) = self.options.content_type {
+            response.headers_mut().insert(header::CONTENT_TYPE, content_type.clone());
+        }
+
+        if let Some(ref accept_encoding) = self.options.accept_encoding {
+            response.headers_mut().insert(ACCEPT_ENCODING_HEADER, accept_encoding.clone());
         }
     }
 }
@@ -30,39 +72,89 @@ impl<T, E, S> Future for ResponseFuture<T, E>
 where T: Future<Item = Response<S>,
                Error = ::Error>,
       E: Encoder,
-      S: Stream<Item = E::Item>,
+      S: Stream<Item = E::Item, Error = ::Error>,
 {
     type Item = http::Response<Encode<E, S>>;
     type Error = h2::Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let response = try_ready!(self.poll_response());
+
+        // The response body cancels the call from now on.
+        self.options.cancel = None;
+
+        Ok(response.into())
+    }
+}
+
+impl<T, E> Drop for ResponseFuture<T, E> {
+    fn drop(&mut self) {
+        // The response future is dropped before resolving when the client
+        // resets the stream.
+        if let Some(ref cancel) = self.options.cancel {
+            trace!("response future dropped before the response was sent");
+            cancel.cancel();
+        }
+    }
+}
+
+impl<T, E, S> ResponseFuture<T, E>
+where T: Future<Item = Response<S>,
+               Error = ::Error>,
+      E: Encoder,
+      S: Stream<Item = E::Item, Error = ::Error>,
+{
+    fn poll_response(&mut self) -> Poll<http::Response<Encode<E, S>>, h2::Error> {
         // Get the gRPC response
-        let response = match self.inner.poll() {
+        let response = match self.inner {
+            Ok(ref mut inner) => inner.poll(),
+            Err((http_status, ref status)) => {
+                let mut response = self.error_response(status.clone());
+                *response.status_mut() = http_status;
+                return Ok(response.into());
+            }
+        };
+
+        let response = match response {
             Ok(Async::Ready(response)) => response,
-            Ok(Async::NotReady) => return Ok(Async::NotReady),
-            Err(e) => {
-                match e {
-                    ::Error::Grpc(status, _) => {
-                        let response = Response::new(Encode::error(status));
-                        return Ok(response.into_http().into());
-                    }
-                    // TODO: Is this correct?
-                    _ => return Err(h2::Reason::INTERNAL_ERROR.into()),
+            Ok(Async::NotReady) => {
+                if !self.deadline.poll_expired() {
+                    return Ok(Async::NotReady);
                 }
+
+                debug!("deadline exceeded before the response was ready");
+
+                return Ok(self.error_response(Status::DEADLINE_EXCEEDED).into());
+            }
+            Err(e) => {
+                debug!("response future failed; err={:?}", e);
+
+                // Report the failure to the client as a trailers-only response.
+                return Ok(self.error_response(e.status()).into());
             }
         };
 
         // Convert to an HTTP response
-        let response = response.into_http();
+        let mut response = response.into_http();
+        self.set_headers(&mut response);
 
         // Map the response body
-        let (head, body) = response.into_parts();
+        let (mut head, body) = response.into_parts();
 
         // Get the encoder
         let encoder = self.encoder.take().expect("encoder consumed");
 
+        let mut options = self.options.clone();
+
+        if let Some(ref compressor) = options.compressor {
+            head.headers.insert(ENCODING_HEADER, HeaderValue::from_static(compressor.name()));
+        }
+
+        options.message_compression = head.extensions.get::<MessageCompression>().cloned();
+        options.trailers = head.extensions.get::<TrailingMetadata>().cloned();
+
         // Encode the body
-        let body = Encode::new(encoder, body, true);
+        let body = Encode::new(encoder, body, options, true);
 
         // Success
         Ok(http::Response::from_parts(head, body).into())
@@ -1,10 +1,11 @@
-use {Request, Response};
+use {Request, Response, Status};
 use super::server_streaming;
-use generic::{Encoder, Encode};
+use generic::{Encoder, Encode, EncodeOptions};
 use generic::server::UnaryService;
 
 use {h2, http};
 use futures::{Future, Stream, Poll};
+use http::StatusCode;
 use tower_ready_service::ReadyService;
 
 use std::fmt;
@@ -35,8 +36,15 @@ where T: UnaryService<Request = S::Item, Response = E::Item>,
       E: Encoder,
       S: Stream<Error = ::Error>,
 {
-    pub fn new(inner: T, request: Request<S>, encoder: E) -> Self {
-        let inner = server_streaming::ResponseFuture::new(Inner(inner), request, encoder);
+    pub fn new(inner: T, request: Request<S>, encoder: E, options: EncodeOptions) -> Self {
+        let inner = server_streaming::ResponseFuture::new(Inner(inner), request, encoder, options);
+        ResponseFuture { inner }
+    }
+
+    /// Create a response future answering with `http_status`, without
+    /// calling the service.
+    pub(crate) fn rejected(http_status: StatusCode, status: Status, options: EncodeOptions) -> Self {
+        let inner = server_streaming::ResponseFuture::rejected(http_status, status, options);
         ResponseFuture { inner }
     }
 }
@@ -0,0 +1,298 @@
+//! The standard gRPC health checking service, `grpc.health.v1.Health`.
+//!
+//! Load balancers probe `Check` and `Watch` to learn whether a server, or an
+//! individual service on it, can handle requests. The status of each service
+//! is set with a `HealthReporter`:
+//!
+//! ```ignore
+//! let (reporter, health) = tower_grpc::health::health_reporter();
+//! reporter.set_serving("helloworld.Greeter");
+//! ```
+
+use {Error, Request, Response, Status};
+
+use futures::{future, Poll, Stream};
+use futures::sync::mpsc;
+use http::HeaderMap;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+mod pb {
+    #![allow(dead_code, missing_debug_implementations, unused_imports)]
+    include!(concat!(env!("OUT_DIR"), "/grpc.health.v1.rs"));
+}
+
+pub use self::pb::{HealthCheckRequest, HealthCheckResponse};
+pub use self::pb::health_check_response::ServingStatus;
+pub use self::pb::client::Health as HealthClient;
+pub use self::pb::server::HealthServer;
+
+/// Sets the serving status of the services reported by a `HealthService`.
+#[derive(Debug, Clone)]
+pub struct HealthReporter {
+    shared: Arc<Mutex<Shared>>,
+}
+
+/// Implements `grpc.health.v1.Health` with the statuses set by a
+/// `HealthReporter`.
+#[derive(Debug, Clone)]
+pub struct HealthService {
+    shared: Arc<Mutex<Shared>>,
+}
+
+/// A stream of serving status updates for a `Watch` call.
+#[derive(Debug)]
+pub struct WatchStream {
+    rx: mpsc::UnboundedReceiver<ServingStatus>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    /// The serving status of each service.
+    statuses: HashMap<String, ServingStatus>,
+
+    /// Active `Watch` calls, by service.
+    watchers: HashMap<String, Vec<mpsc::UnboundedSender<ServingStatus>>>,
+}
+
+/// Returns a `HealthReporter` and a `HealthServer` reporting its statuses.
+///
+/// The server as a whole, named by the empty string, is reported as
+/// `SERVING`. Other services are unknown until a status is set.
+pub fn health_reporter() -> (HealthReporter, HealthServer<HealthService>) {
+    let mut statuses = HashMap::new();
+    statuses.insert(String::new(), ServingStatus::Serving);
+
+    let shared = Arc::new(Mutex::new(Shared {
+        statuses,
+        watchers: HashMap::new(),
+    }));
+
+    let reporter = HealthReporter { shared: shared.clone() };
+    let server = HealthServer::new(HealthService { shared });
+
+    (reporter, server)
+}
+
+// ===== impl HealthReporter =====
+
+impl HealthReporter {
+    /// Report `service` as `SERVING`.
+    pub fn set_serving(&self, service: &str) {
+        self.set_service_status(service, ServingStatus::Serving);
+    }
+
+    /// Report `service` as `NOT_SERVING`.
+    pub fn set_not_serving(&self, service: &str) {
+        self.set_service_status(service, ServingStatus::NotServing);
+    }
+
+    /// Set the status reported for `service`, notifying any `Watch` calls
+    /// if it changed.
+    pub fn set_service_status(&self, service: &str, status: ServingStatus) {
+        let mut shared = lock(&self.shared);
+
+        let previous = shared.statuses.insert(service.to_string(), status);
+
+        if previous == Some(status) {
+            return;
+        }
+
+        let ended = match shared.watchers.get_mut(service) {
+            Some(watchers) => {
+                // Watchers whose call has ended are dropped.
+                watchers.retain(|tx| tx.unbounded_send(status).is_ok());
+                watchers.is_empty()
+            }
+            None => false,
+        };
+
+        if ended {
+            shared.watchers.remove(service);
+        }
+    }
+}
+
+// ===== impl HealthService =====
+
+impl pb::server::Health for HealthService {
+    type CheckFuture = future::FutureResult<Response<HealthCheckResponse>, Error>;
+    type WatchStream = WatchStream;
+    type WatchFuture = future::FutureResult<Response<WatchStream>, Error>;
+
+    fn check(&mut self, request: Request<HealthCheckRequest>) -> Self::CheckFuture {
+        let service = &request.get_ref().service;
+
+        match lock(&self.shared).statuses.get(service) {
+            Some(&status) => {
+                future::ok(Response::new(HealthCheckResponse {
+                    status: status as i32,
+                }))
+            }
+            None => {
+                let status = Status::NOT_FOUND
+                    .with_message(format!("unknown service: {}", service));
+                future::err(Error::Grpc(status, HeaderMap::new()))
+            }
+        }
+    }
+
+    fn watch(&mut self, request: Request<HealthCheckRequest>) -> Self::WatchFuture {
+        let service = request.into_inner().service;
+        let (tx, rx) = mpsc::unbounded();
+
+        let mut shared = lock(&self.shared);
+
+        // The current status is always sent first.
+        let status = shared.statuses.get(&service)
+            .cloned()
+            .unwrap_or(ServingStatus::ServiceUnknown);
+
+        shared.remove_ended_watchers();
+
+        if tx.unbounded_send(status).is_ok() {
+            shared.watchers.entry(service)
+                .or_insert_with(Vec::new)
+                .push(tx);
+        }
+
+        future::ok(Response::new(WatchStream { rx }))
+    }
+}
+
+// ===== impl Shared =====
+
+impl Shared {
+    /// Drop the senders of `Watch` calls that have ended.
+    ///
+    /// Services that are never set would otherwise keep the watchers of
+    /// every ended call.
+    fn remove_ended_watchers(&mut self) {
+        self.watchers.retain(|_, watchers| {
+            watchers.retain(|tx| !tx.is_closed());
+            !watchers.is_empty()
+        });
+    }
+}
+
+// ===== impl WatchStream =====
+
+impl Stream for WatchStream {
+    type Item = HealthCheckResponse;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        // The receiver never fails.
+        let status = try_ready!(self.rx.poll().map_err(Error::Inner));
+
+        Ok(status.map(|status| HealthCheckResponse {
+            status: status as i32,
+        }).into())
+    }
+}
+
+// ===== utility fns =====
+
+fn lock(shared: &Mutex<Shared>) -> MutexGuard<Shared> {
+    // A panic while holding the lock cannot leave the statuses invalid.
+    shared.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::pb::server::Health;
+    use {Code, MetadataMap};
+
+    use futures::{Async, Future};
+    use http;
+    use http::header;
+    use tower::Service;
+    use tower_h2::{Body, RecvBody};
+
+    fn request(service: &str) -> Request<HealthCheckRequest> {
+        Request::new(HealthCheckRequest { service: service.to_string() })
+    }
+
+    fn service(reporter: &HealthReporter) -> HealthService {
+        HealthService { shared: reporter.shared.clone() }
+    }
+
+    #[test]
+    fn watch_sends_status_changes() {
+        let (reporter, _) = health_reporter();
+
+        let mut updates = service(&reporter).watch(request("a"))
+            .wait()
+            .unwrap()
+            .into_inner()
+            .wait();
+
+        assert_eq!(updates.next().unwrap().unwrap().status, ServingStatus::ServiceUnknown as i32);
+
+        reporter.set_serving("a");
+        reporter.set_serving("a");
+        reporter.set_not_serving("a");
+
+        assert_eq!(updates.next().unwrap().unwrap().status, ServingStatus::Serving as i32);
+        assert_eq!(updates.next().unwrap().unwrap().status, ServingStatus::NotServing as i32);
+    }
+
+    #[test]
+    fn ended_watchers_are_removed() {
+        let (reporter, _) = health_reporter();
+        let mut service = service(&reporter);
+
+        let watch = service.watch(request("a")).wait().unwrap();
+        assert_eq!(lock(&reporter.shared).watchers["a"].len(), 1);
+
+        drop(watch);
+        reporter.set_serving("a");
+        assert!(!lock(&reporter.shared).watchers.contains_key("a"));
+
+        // Watchers of a service that is never set are removed when the
+        // next call starts.
+        drop(service.watch(request("unknown")).wait().unwrap());
+        let _watch = service.watch(request("b")).wait().unwrap();
+
+        let shared = lock(&reporter.shared);
+        assert!(!shared.watchers.contains_key("unknown"));
+        assert_eq!(shared.watchers["b"].len(), 1);
+    }
+
+    #[test]
+    fn interceptor_rejects_requests() {
+        let (reporter, _) = health_reporter();
+
+        let interceptor = |path: &str, metadata: &MetadataMap| -> Result<(), Status> {
+            match metadata.get("authorization") {
+                Some(_) => Ok(()),
+                None => Err(Status::UNAUTHENTICATED.with_message(format!("no token for {}", path))),
+            }
+        };
+        let mut server = HealthServer::with_interceptor(service(&reporter), interceptor);
+
+        let request = http::Request::post("/grpc.health.v1.Health/Check")
+            .header(header::CONTENT_TYPE, "application/grpc")
+            .header(header::TE, "trailers")
+            .body(RecvBody::default())
+            .unwrap();
+
+        let response = server.call(request).wait().expect("response");
+        let (head, mut body) = response.into_parts();
+
+        // The client reads the status from the trailers of a gRPC response.
+        assert_eq!(head.status, http::StatusCode::OK);
+        assert_eq!(head.headers[header::CONTENT_TYPE], "application/grpc");
+        assert!(!body.is_end_stream());
+        assert_eq!(body.poll_data().expect("poll_data"), Async::Ready(None));
+
+        let status = match body.poll_trailers().expect("poll_trailers") {
+            Async::Ready(Some(trailers)) => Status::from_header_map(&trailers).unwrap(),
+            trailers => panic!("unexpected trailers: {:?}", trailers),
+        };
+        assert_eq!(status.code(), Code::UNAUTHENTICATED);
+        assert_eq!(status.message(), "no token for /grpc.health.v1.Health/Check");
+    }
+}
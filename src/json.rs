@@ -0,0 +1,183 @@
+//! A JSON codec, for calling services with human-readable payloads.
+//!
+//! Messages are encoded with serde and sent as `application/grpc+json`.
+
+use Status;
+use generic::{EncodeBuf, DecodeBuf};
+
+use bytes::{Buf, BufMut};
+use http::HeaderMap;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json;
+
+use std::marker::PhantomData;
+
+/// JSON codec, encoding `T` and decoding `U`
+#[derive(Debug)]
+pub struct JsonCodec<T, U>(PhantomData<(T, U)>);
+
+#[derive(Debug)]
+pub struct JsonEncoder<T>(PhantomData<T>);
+
+#[derive(Debug)]
+pub struct JsonDecoder<T>(PhantomData<T>);
+
+// ===== impl JsonCodec =====
+
+impl<T, U> JsonCodec<T, U>
+where T: Serialize,
+      U: DeserializeOwned,
+{
+    /// Create a new JSON codec
+    pub fn new() -> Self {
+        JsonCodec(PhantomData)
+    }
+}
+
+impl<T, U> ::generic::Codec for JsonCodec<T, U>
+where T: Serialize,
+      U: DeserializeOwned,
+{
+    /// JSON gRPC content type
+    const CONTENT_TYPE: &'static str = "application/grpc+json";
+
+    type Encode = T;
+    type Encoder = JsonEncoder<T>;
+    type Decode = U;
+    type Decoder = JsonDecoder<U>;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        JsonEncoder(PhantomData)
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        JsonDecoder(PhantomData)
+    }
+}
+
+impl<T, U> Clone for JsonCodec<T, U> {
+    fn clone(&self) -> Self {
+        JsonCodec(PhantomData)
+    }
+}
+
+// ===== impl JsonEncoder =====
+
+impl<T> JsonEncoder<T>
+where T: Serialize,
+{
+    /// Returns a new encoder
+    pub fn new() -> Self {
+        JsonEncoder(PhantomData)
+    }
+}
+
+impl<T> ::generic::Encoder for JsonEncoder<T>
+where T: Serialize,
+{
+    type Item = T;
+
+    fn encode(&mut self, item: T, buf: &mut EncodeBuf) -> Result<(), ::Error> {
+        let json = serde_json::to_vec(&item)
+            .map_err(|e| internal(format!("failed to encode JSON message: {}", e)))?;
+
+        buf.reserve(json.len());
+        buf.put_slice(&json);
+        Ok(())
+    }
+}
+
+impl<T> Clone for JsonEncoder<T> {
+    fn clone(&self) -> Self {
+        JsonEncoder(PhantomData)
+    }
+}
+
+// ===== impl JsonDecoder =====
+
+impl<T> JsonDecoder<T>
+where T: DeserializeOwned,
+{
+    /// Returns a new decoder
+    pub fn new() -> Self {
+        JsonDecoder(PhantomData)
+    }
+}
+
+impl<T> ::generic::Decoder for JsonDecoder<T>
+where T: DeserializeOwned,
+{
+    type Item = T;
+
+    fn decode(&mut self, buf: &mut DecodeBuf) -> Result<T, ::Error> {
+        let mut json = vec![0; buf.remaining()];
+        buf.copy_to_slice(&mut json);
+
+        serde_json::from_slice(&json)
+            .map_err(|e| internal(format!("failed to decode JSON message: {}", e)))
+    }
+}
+
+impl<T> Clone for JsonDecoder<T> {
+    fn clone(&self) -> Self {
+        JsonDecoder(PhantomData)
+    }
+}
+
+// ===== utility fns =====
+
+fn internal(message: String) -> ::Error {
+    ::Error::Grpc(Status::INTERNAL.with_message(message), HeaderMap::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Code;
+    use generic::{Codec, Encode, EncodeOptions, Streaming};
+    use test_util::{frame, TestBody};
+
+    use futures::{stream, Async, Stream};
+    use serde_json::Value;
+    use tower_h2::Body;
+
+    type ValueCodec = JsonCodec<Value, Value>;
+
+    #[test]
+    fn encode_decode_round_trip() {
+        let mut codec = ValueCodec::new();
+        let value: Value = serde_json::from_str(r#"{ "name": "tower", "count": 2, "tags": ["a", "b"] }"#)
+            .unwrap();
+
+        let messages = stream::iter_ok::<_, ::Error>(vec![value.clone()]);
+        let mut encode = Encode::new(codec.encoder(), messages, EncodeOptions::default(), false);
+
+        let frame = match encode.poll_data().expect("poll_data") {
+            Async::Ready(Some(frame)) => frame,
+            res => panic!("unexpected result: {:?}", res),
+        };
+        assert_eq!(&frame[5..], &serde_json::to_vec(&value).unwrap()[..]);
+
+        let mut messages = Streaming::new(codec.decoder(), TestBody::new(vec![frame]), false);
+        assert_eq!(messages.poll().unwrap(), Async::Ready(Some(value)));
+        assert_eq!(messages.poll().unwrap(), Async::Ready(None));
+    }
+
+    #[test]
+    fn invalid_json_is_internal() {
+        let mut codec = ValueCodec::new();
+        let body = TestBody::new(vec![frame(b"{ not json")]);
+        let mut messages = Streaming::new(codec.decoder(), body, false);
+
+        match messages.poll() {
+            Err(::Error::Grpc(status, _)) => assert_eq!(status.code(), Code::INTERNAL),
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn content_type() {
+        assert_eq!(<ValueCodec as Codec>::CONTENT_TYPE, "application/grpc+json");
+    }
+}
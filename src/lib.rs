@@ -1,30 +1,52 @@
 #![deny(warnings, missing_debug_implementations)]
 //#![deny(missing_docs)]
 
+extern crate base64;
 extern crate bytes;
+#[cfg(any(feature = "gzip", feature = "deflate"))]
+extern crate flate2;
 #[macro_use]
 extern crate futures;
 extern crate http;
 extern crate h2;
 #[macro_use]
 extern crate log;
+extern crate tokio_timer;
 extern crate tower;
 extern crate tower_ready_service;
 extern crate tower_h2;
 
 #[cfg(feature = "protobuf")]
 extern crate prost;
+#[cfg(feature = "protobuf")]
+#[macro_use]
+extern crate prost_derive;
+#[cfg(feature = "reflection")]
+extern crate prost_types;
+#[cfg(feature = "json")]
+extern crate serde;
+#[cfg(feature = "json")]
+extern crate serde_json;
 
 pub mod client;
 pub mod generic;
+pub mod web;
 
+mod cancel;
+mod deadline;
 mod error;
+mod metadata;
 mod request;
 mod response;
 mod status;
 
+#[cfg(test)]
+mod test_util;
+
+pub use cancel::{CancelHandle, Canceled};
 pub use error::{Error, ProtocolError};
-pub use status::{Code, Status};
+pub use metadata::{InvalidMetadata, MetadataMap, TrailingMetadata};
+pub use status::{Code, ParseCodeError, Status};
 pub use request::Request;
 pub use response::Response;
 
@@ -38,6 +60,24 @@ pub mod codegen;
 #[cfg(feature = "protobuf")]
 mod codec;
 
+#[cfg(feature = "protobuf")]
+pub mod error_details;
+
+#[cfg(feature = "json")]
+pub mod json;
+
+#[cfg(feature = "health")]
+pub mod health;
+
+#[cfg(feature = "reflection")]
+pub mod reflection;
+
+/// Lets code generated by `tower-grpc-build` refer to this crate.
+#[cfg(any(feature = "health", feature = "reflection"))]
+mod tower_grpc {
+    pub use ::codegen;
+}
+
 #[cfg(feature = "protobuf")]
 pub use codec::{Encode, Streaming};
 
@@ -0,0 +1,394 @@
+//! gRPC metadata.
+//!
+//! Metadata is carried in HTTP/2 headers and trailers. Values of keys ending
+//! in `-bin` are binary, and base64 encoded on the wire. All other values are
+//! printable ASCII.
+
+use base64;
+use http::HeaderMap;
+use http::header::{HeaderName, HeaderValue};
+
+use std::error;
+use std::fmt;
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+const BINARY_SUFFIX: &[u8] = b"-bin";
+
+/// A map of gRPC metadata.
+///
+/// Headers used by gRPC and HTTP/2 themselves (`grpc-*`, `te` and
+/// `content-type`) cannot be read or written through this map.
+#[derive(Debug, Clone, Default)]
+pub struct MetadataMap {
+    headers: HeaderMap,
+}
+
+/// A handle for setting the trailing metadata of a response.
+///
+/// Trailers are sent after the last response message, so a handle moved
+/// into a response stream can add metadata computed while streaming.
+#[derive(Debug, Clone, Default)]
+pub struct TrailingMetadata {
+    inner: Arc<Mutex<MetadataMap>>,
+}
+
+/// An error returned when metadata cannot be stored or decoded.
+#[derive(Debug)]
+pub struct InvalidMetadata {
+    kind: Kind,
+}
+
+#[derive(Debug)]
+enum Kind {
+    Key,
+    ReservedKey,
+    AsciiKey,
+    BinaryKey,
+    AsciiValue,
+    Base64,
+}
+
+// ===== impl MetadataMap =====
+
+impl MetadataMap {
+    /// Create an empty `MetadataMap`.
+    pub fn new() -> Self {
+        MetadataMap::from_headers(HeaderMap::new())
+    }
+
+    pub(crate) fn from_headers(headers: HeaderMap) -> Self {
+        MetadataMap { headers }
+    }
+
+    pub(crate) fn into_headers(self) -> HeaderMap {
+        self.headers
+    }
+
+    pub(crate) fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    pub(crate) fn headers_mut(&mut self) -> &mut HeaderMap {
+        &mut self.headers
+    }
+
+    /// Returns the number of metadata values.
+    pub fn len(&self) -> usize {
+        self.headers.iter()
+            .filter(|&(key, _)| !is_reserved(key.as_str()))
+            .count()
+    }
+
+    /// Returns true if the map has no metadata.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns true if the map has a value for `key`.
+    pub fn contains_key(&self, key: &str) -> bool {
+        !is_reserved(key) && self.headers.contains_key(key)
+    }
+
+    /// Returns an iterator over the distinct metadata keys.
+    pub fn keys<'a>(&'a self) -> impl Iterator<Item = &'a str> + 'a {
+        self.headers.keys()
+            .map(HeaderName::as_str)
+            .filter(|key| !is_reserved(key))
+    }
+
+    /// Get the first ASCII value of `key`.
+    ///
+    /// Returns `None` for `-bin` keys, which must be read with `get_bin`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.get_all(key).next()
+    }
+
+    /// Get all ASCII values of `key`.
+    pub fn get_all<'a>(&'a self, key: &str) -> impl Iterator<Item = &'a str> + 'a {
+        let values = if is_reserved(key) || is_binary(key) {
+            None
+        } else {
+            Some(self.headers.get_all(key))
+        };
+
+        values.into_iter()
+            .flat_map(|values| values)
+            .filter_map(|value| value.to_str().ok())
+    }
+
+    /// Get and decode the first binary value of `key`.
+    ///
+    /// Returns `None` for keys that do not end in `-bin`.
+    pub fn get_bin(&self, key: &str) -> Option<Result<Vec<u8>, InvalidMetadata>> {
+        self.get_all_bin(key).next()
+    }
+
+    /// Get and decode all binary values of `key`.
+    pub fn get_all_bin<'a>(&'a self, key: &str)
+        -> impl Iterator<Item = Result<Vec<u8>, InvalidMetadata>> + 'a
+    {
+        let values = if !is_reserved(key) && is_binary(key) {
+            Some(self.headers.get_all(key))
+        } else {
+            None
+        };
+
+        // A peer may join several values with commas.
+        values.into_iter()
+            .flat_map(|values| values)
+            .flat_map(|value| value.as_bytes().split(|&b| b == b','))
+            .map(|value| {
+                base64::decode(trim(value))
+                    .map_err(|_| InvalidMetadata::new(Kind::Base64))
+            })
+    }
+
+    /// Set the ASCII value of `key`, replacing any existing values.
+    pub fn insert(&mut self, key: &str, value: &str) -> Result<(), InvalidMetadata> {
+        let (key, value) = ascii_entry(key, value)?;
+        self.headers.insert(key, value);
+        Ok(())
+    }
+
+    /// Add an ASCII value to `key`.
+    pub fn append(&mut self, key: &str, value: &str) -> Result<(), InvalidMetadata> {
+        let (key, value) = ascii_entry(key, value)?;
+        self.headers.append(key, value);
+        Ok(())
+    }
+
+    /// Set the binary value of `key`, replacing any existing values.
+    pub fn insert_bin(&mut self, key: &str, value: &[u8]) -> Result<(), InvalidMetadata> {
+        let (key, value) = binary_entry(key, value)?;
+        self.headers.insert(key, value);
+        Ok(())
+    }
+
+    /// Add a binary value to `key`.
+    pub fn append_bin(&mut self, key: &str, value: &[u8]) -> Result<(), InvalidMetadata> {
+        let (key, value) = binary_entry(key, value)?;
+        self.headers.append(key, value);
+        Ok(())
+    }
+
+    /// Remove all values of `key`, returning true if there were any.
+    pub fn remove(&mut self, key: &str) -> bool {
+        !is_reserved(key) && self.headers.remove(key).is_some()
+    }
+}
+
+// ===== impl TrailingMetadata =====
+
+impl TrailingMetadata {
+    /// Returns a new handle with empty trailing metadata.
+    pub fn new() -> Self {
+        TrailingMetadata::default()
+    }
+
+    /// Modify the trailing metadata.
+    pub fn update<F, R>(&self, f: F) -> R
+    where F: FnOnce(&mut MetadataMap) -> R,
+    {
+        // A panic while holding the lock cannot leave the map invalid.
+        let mut metadata = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        f(&mut metadata)
+    }
+
+    /// Take the trailing metadata, leaving it empty.
+    pub(crate) fn take(&self) -> MetadataMap {
+        self.update(|metadata| mem::replace(metadata, MetadataMap::new()))
+    }
+}
+
+// ===== impl InvalidMetadata =====
+
+impl InvalidMetadata {
+    fn new(kind: Kind) -> Self {
+        InvalidMetadata { kind }
+    }
+}
+
+impl fmt::Display for InvalidMetadata {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self.kind {
+            Kind::Key => "invalid metadata key",
+            Kind::ReservedKey => "metadata key is reserved",
+            Kind::AsciiKey => "ASCII metadata key must not end in \"-bin\"",
+            Kind::BinaryKey => "binary metadata key must end in \"-bin\"",
+            Kind::AsciiValue => "metadata value is not printable ASCII",
+            Kind::Base64 => "binary metadata value is not valid base64",
+        })
+    }
+}
+
+impl error::Error for InvalidMetadata {
+    fn description(&self) -> &str {
+        "invalid metadata"
+    }
+}
+
+// ===== utility fns =====
+
+/// Returns true if `key` is used by gRPC or HTTP/2 itself.
+fn is_reserved(key: &str) -> bool {
+    let key = key.as_bytes();
+
+    (key.len() >= 5 && key[..5].eq_ignore_ascii_case(b"grpc-")) ||
+        key.eq_ignore_ascii_case(b"te") ||
+        key.eq_ignore_ascii_case(b"content-type")
+}
+
+fn is_binary(key: &str) -> bool {
+    let key = key.as_bytes();
+
+    key.len() > BINARY_SUFFIX.len() &&
+        key[key.len() - BINARY_SUFFIX.len()..].eq_ignore_ascii_case(BINARY_SUFFIX)
+}
+
+fn metadata_key(key: &str) -> Result<HeaderName, InvalidMetadata> {
+    if is_reserved(key) {
+        return Err(InvalidMetadata::new(Kind::ReservedKey));
+    }
+
+    HeaderName::from_bytes(key.as_bytes())
+        .map_err(|_| InvalidMetadata::new(Kind::Key))
+}
+
+fn ascii_entry(key: &str, value: &str) -> Result<(HeaderName, HeaderValue), InvalidMetadata> {
+    if is_binary(key) {
+        return Err(InvalidMetadata::new(Kind::AsciiKey));
+    }
+
+    let key = metadata_key(key)?;
+
+    if !value.bytes().all(|b| b >= 0x20 && b <= 0x7e) {
+        return Err(InvalidMetadata::new(Kind::AsciiValue));
+    }
+
+    let value = HeaderValue::from_str(value)
+        .map_err(|_| InvalidMetadata::new(Kind::AsciiValue))?;
+
+    Ok((key, value))
+}
+
+fn binary_entry(key: &str, value: &[u8]) -> Result<(HeaderName, HeaderValue), InvalidMetadata> {
+    if !is_binary(key) {
+        return Err(InvalidMetadata::new(Kind::BinaryKey));
+    }
+
+    let key = metadata_key(key)?;
+
+    // Values are sent unpadded, as recommended by the gRPC spec.
+    let value = base64::encode_config(value, base64::STANDARD_NO_PAD);
+    let value = HeaderValue::from_str(&value)
+        .expect("base64 is a valid header value");
+
+    Ok((key, value))
+}
+
+fn trim(value: &[u8]) -> &[u8] {
+    let start = value.iter().position(|&b| b != b' ').unwrap_or(value.len());
+    let end = value.iter().rposition(|&b| b != b' ').map(|i| i + 1).unwrap_or(start);
+    &value[start..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(headers: &[(&'static str, &'static str)]) -> MetadataMap {
+        let mut map = HeaderMap::new();
+        for &(key, value) in headers {
+            map.append(key, HeaderValue::from_static(value));
+        }
+        MetadataMap::from_headers(map)
+    }
+
+    #[test]
+    fn reserved_headers_are_hidden() {
+        let map = metadata(&[
+            ("grpc-status", "0"),
+            ("grpc-timeout", "1S"),
+            ("te", "trailers"),
+            ("content-type", "application/grpc"),
+            ("user-agent", "test"),
+        ]);
+
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.keys().collect::<Vec<_>>(), ["user-agent"]);
+        assert_eq!(map.get("user-agent"), Some("test"));
+
+        for &key in &["grpc-status", "GRPC-Timeout", "te", "Content-Type"] {
+            assert!(!map.contains_key(key), "key={}", key);
+            assert_eq!(map.get(key), None, "key={}", key);
+        }
+    }
+
+    #[test]
+    fn reserved_headers_cannot_be_changed() {
+        let mut map = metadata(&[("grpc-status", "0")]);
+
+        for &key in &["grpc-status", "grpc-message", "te", "content-type"] {
+            assert!(map.insert(key, "1").is_err(), "key={}", key);
+            assert!(map.append(key, "1").is_err(), "key={}", key);
+        }
+        assert!(map.insert_bin("grpc-status-details-bin", b"1").is_err());
+        assert!(!map.remove("grpc-status"));
+
+        assert_eq!(map.headers()["grpc-status"], "0");
+    }
+
+    #[test]
+    fn binary_values_are_base64() {
+        let mut map = MetadataMap::new();
+        map.insert_bin("trace-bin", &[0, 1, 2, 0xff]).unwrap();
+
+        // Sent unpadded.
+        assert_eq!(map.headers()["trace-bin"], "AAEC/w");
+        assert_eq!(map.get_bin("trace-bin").unwrap().unwrap(), [0, 1, 2, 0xff]);
+
+        // Binary and ASCII values are read and written separately.
+        assert_eq!(map.get("trace-bin"), None);
+        assert!(map.get_bin("trace").is_none());
+        assert!(map.insert("trace-bin", "x").is_err());
+        assert!(map.insert_bin("trace", b"x").is_err());
+    }
+
+    #[test]
+    fn binary_values_accept_padding() {
+        let map = metadata(&[("trace-bin", "AAEC/w==")]);
+        assert_eq!(map.get_bin("trace-bin").unwrap().unwrap(), [0, 1, 2, 0xff]);
+    }
+
+    #[test]
+    fn invalid_base64_is_an_error() {
+        let map = metadata(&[("trace-bin", "not base64!")]);
+        assert!(map.get_bin("trace-bin").unwrap().is_err());
+    }
+
+    #[test]
+    fn binary_values_split_on_commas() {
+        let map = metadata(&[("trace-bin", "AQ, Ag=="), ("trace-bin", "Aw")]);
+
+        let values = map.get_all_bin("trace-bin")
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(values, [vec![1], vec![2], vec![3]]);
+    }
+
+    #[test]
+    fn ascii_values_are_not_split() {
+        let map = metadata(&[("accept", "a, b"), ("accept", "c")]);
+        assert_eq!(map.get_all("accept").collect::<Vec<_>>(), ["a, b", "c"]);
+    }
+
+    #[test]
+    fn ascii_values_must_be_printable() {
+        let mut map = MetadataMap::new();
+        assert!(map.insert("key", "tab\there").is_err());
+        assert!(map.insert("key", "caf\u{e9}").is_err());
+        assert!(map.insert("bad key", "value").is_err());
+        assert!(map.is_empty());
+    }
+}
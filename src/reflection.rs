@@ -0,0 +1,448 @@
+//! The gRPC server reflection service, `grpc.reflection.v1alpha.ServerReflection`.
+//!
+//! Tools such as `grpcurl` use reflection to list the services of a server
+//! and fetch the protobuf descriptors needed to call them. The service
+//! answers from serialized `FileDescriptorSet`s, like the one embedded by
+//! `tower-grpc-build` when `Config::file_descriptor_set` is enabled:
+//!
+//! ```ignore
+//! let reflection = tower_grpc::reflection::Builder::new()
+//!     .register_file_descriptor_set(hello_world::FILE_DESCRIPTOR_SET)?
+//!     .build();
+//! ```
+
+use {Code, Error, Request, Response};
+use codec::Streaming;
+
+use futures::{future, Poll, Stream};
+use prost::{DecodeError, Message};
+use prost_types::{DescriptorProto, EnumDescriptorProto, FileDescriptorProto, FileDescriptorSet};
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+
+mod pb {
+    #![allow(dead_code, missing_debug_implementations, unused_imports)]
+    include!(concat!(env!("OUT_DIR"), "/grpc.reflection.v1alpha.rs"));
+}
+
+pub use self::pb::{ServerReflectionRequest, ServerReflectionResponse};
+pub use self::pb::client::ServerReflection as ServerReflectionClient;
+pub use self::pb::server::ServerReflectionServer;
+
+use self::pb::{ErrorResponse, FileDescriptorResponse, ListServiceResponse, ServiceResponse};
+use self::pb::server_reflection_request::MessageRequest;
+use self::pb::server_reflection_response::MessageResponse;
+
+/// Collects the file descriptors described by a `ReflectionService`.
+#[derive(Debug, Default)]
+pub struct Builder {
+    files: Vec<FileDescriptorProto>,
+}
+
+/// Implements `grpc.reflection.v1alpha.ServerReflection` for the files
+/// registered with a `Builder`.
+#[derive(Debug, Clone)]
+pub struct ReflectionService {
+    index: Arc<Index>,
+}
+
+/// The responses to a `ServerReflectionInfo` call, one per request.
+#[derive(Debug)]
+pub struct InfoStream {
+    requests: Streaming<ServerReflectionRequest>,
+    index: Arc<Index>,
+}
+
+#[derive(Debug, Default)]
+struct Index {
+    /// Registered files, by name.
+    files: HashMap<String, File>,
+
+    /// The name of the file defining each fully-qualified symbol.
+    symbols: HashMap<String, String>,
+
+    /// Fully-qualified service names, sorted.
+    services: Vec<String>,
+}
+
+#[derive(Debug)]
+struct File {
+    /// The serialized `FileDescriptorProto`.
+    encoded: Vec<u8>,
+
+    /// The names of the files imported by this one.
+    dependencies: Vec<String>,
+}
+
+// ===== impl Builder =====
+
+impl Builder {
+    /// Create a builder with no files registered.
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    /// Register every file in a serialized `FileDescriptorSet`.
+    ///
+    /// The set should include the files' imports, as written by
+    /// `protoc --include_imports`, so clients can resolve every type.
+    pub fn register_file_descriptor_set(&mut self, encoded: &[u8])
+        -> Result<&mut Self, DecodeError>
+    {
+        let set = FileDescriptorSet::decode(encoded)?;
+        self.files.extend(set.file);
+        Ok(self)
+    }
+
+    /// Register a single file descriptor.
+    pub fn register_file_descriptor(&mut self, file: FileDescriptorProto) -> &mut Self {
+        self.files.push(file);
+        self
+    }
+
+    /// Returns a `ServerReflectionServer` describing the registered files.
+    pub fn build(&self) -> ServerReflectionServer<ReflectionService> {
+        ServerReflectionServer::new(ReflectionService {
+            index: Arc::new(Index::new(&self.files)),
+        })
+    }
+}
+
+// ===== impl ReflectionService =====
+
+impl pb::server::ServerReflection for ReflectionService {
+    type ServerReflectionInfoStream = InfoStream;
+    type ServerReflectionInfoFuture = future::FutureResult<Response<InfoStream>, Error>;
+
+    fn server_reflection_info(&mut self, request: Request<Streaming<ServerReflectionRequest>>)
+        -> Self::ServerReflectionInfoFuture
+    {
+        future::ok(Response::new(InfoStream {
+            requests: request.into_inner(),
+            index: self.index.clone(),
+        }))
+    }
+}
+
+// ===== impl InfoStream =====
+
+impl Stream for InfoStream {
+    type Item = ServerReflectionResponse;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let request = try_ready!(self.requests.poll());
+
+        Ok(request.map(|request| self.index.respond(request)).into())
+    }
+}
+
+// ===== impl Index =====
+
+impl Index {
+    fn new(files: &[FileDescriptorProto]) -> Self {
+        let mut index = Index::default();
+
+        for file in files {
+            index.add_file(file);
+        }
+
+        index.services.sort();
+        index.services.dedup();
+        index
+    }
+
+    fn add_file(&mut self, file: &FileDescriptorProto) {
+        let file_name = name(&file.name).to_string();
+        let package = name(&file.package);
+
+        for service in &file.service {
+            let service_name = qualify(package, name(&service.name));
+
+            for method in &service.method {
+                let method_name = qualify(&service_name, name(&method.name));
+                self.symbols.insert(method_name, file_name.clone());
+            }
+
+            self.symbols.insert(service_name.clone(), file_name.clone());
+            self.services.push(service_name);
+        }
+
+        self.add_messages(package, &file.message_type, &file_name);
+        self.add_enums(package, &file.enum_type, &file_name);
+
+        let mut encoded = Vec::with_capacity(file.encoded_len());
+        file.encode(&mut encoded)
+            .expect("a Vec has enough capacity for any message");
+
+        self.files.insert(file_name, File {
+            encoded,
+            dependencies: file.dependency.clone(),
+        });
+    }
+
+    fn add_messages(&mut self, scope: &str, messages: &[DescriptorProto], file_name: &str) {
+        for message in messages {
+            let message_name = qualify(scope, name(&message.name));
+
+            self.add_messages(&message_name, &message.nested_type, file_name);
+            self.add_enums(&message_name, &message.enum_type, file_name);
+
+            self.symbols.insert(message_name, file_name.to_string());
+        }
+    }
+
+    fn add_enums(&mut self, scope: &str, enums: &[EnumDescriptorProto], file_name: &str) {
+        for enumeration in enums {
+            let enum_name = qualify(scope, name(&enumeration.name));
+            self.symbols.insert(enum_name, file_name.to_string());
+        }
+    }
+
+    fn respond(&self, request: ServerReflectionRequest) -> ServerReflectionResponse {
+        let response = match request.message_request {
+            Some(MessageRequest::FileByFilename(ref file_name)) => {
+                self.file_descriptors(file_name)
+            }
+            Some(MessageRequest::FileContainingSymbol(ref symbol)) => {
+                match self.symbols.get(symbol) {
+                    Some(file_name) => self.file_descriptors(file_name),
+                    None => error(Code::NOT_FOUND, format!("symbol not found: {}", symbol)),
+                }
+            }
+            Some(MessageRequest::ListServices(_)) => {
+                let service = self.services.iter()
+                    .map(|name| ServiceResponse { name: name.clone() })
+                    .collect();
+
+                MessageResponse::ListServicesResponse(ListServiceResponse { service })
+            }
+            Some(MessageRequest::FileContainingExtension(_)) |
+            Some(MessageRequest::AllExtensionNumbersOfType(_)) => {
+                error(Code::UNIMPLEMENTED, "extensions are not supported".to_string())
+            }
+            None => {
+                error(Code::INVALID_ARGUMENT, "missing message request".to_string())
+            }
+        };
+
+        ServerReflectionResponse {
+            valid_host: request.host.clone(),
+            original_request: Some(request),
+            message_response: Some(response),
+        }
+    }
+
+    /// Returns the file named `file_name` followed by its transitive imports.
+    fn file_descriptors(&self, file_name: &str) -> MessageResponse {
+        if !self.files.contains_key(file_name) {
+            return error(Code::NOT_FOUND, format!("file not found: {}", file_name));
+        }
+
+        let mut file_descriptor_proto = Vec::new();
+        let mut seen = HashSet::new();
+        let mut pending = VecDeque::new();
+
+        seen.insert(file_name);
+        pending.push_back(file_name);
+
+        while let Some(file_name) = pending.pop_front() {
+            // Imports that were not registered are left for the client to
+            // resolve.
+            let file = match self.files.get(file_name) {
+                Some(file) => file,
+                None => continue,
+            };
+
+            file_descriptor_proto.push(file.encoded.clone());
+
+            for dependency in &file.dependencies {
+                if seen.insert(dependency) {
+                    pending.push_back(dependency);
+                }
+            }
+        }
+
+        MessageResponse::FileDescriptorResponse(FileDescriptorResponse {
+            file_descriptor_proto,
+        })
+    }
+}
+
+// ===== utility fns =====
+
+fn name(name: &Option<String>) -> &str {
+    name.as_ref().map(String::as_str).unwrap_or("")
+}
+
+fn qualify(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", scope, name)
+    }
+}
+
+fn error(code: Code, error_message: String) -> MessageResponse {
+    MessageResponse::ErrorResponse(ErrorResponse {
+        error_code: code.to_i32(),
+        error_message,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use prost_types::{MethodDescriptorProto, ServiceDescriptorProto};
+
+    fn file(name: &str, package: &str, dependencies: &[&str]) -> FileDescriptorProto {
+        FileDescriptorProto {
+            name: Some(name.to_string()),
+            package: Some(package.to_string()),
+            dependency: dependencies.iter().map(|dependency| dependency.to_string()).collect(),
+            ..FileDescriptorProto::default()
+        }
+    }
+
+    fn message(name: &str) -> DescriptorProto {
+        DescriptorProto {
+            name: Some(name.to_string()),
+            ..DescriptorProto::default()
+        }
+    }
+
+    fn enumeration(name: &str) -> EnumDescriptorProto {
+        EnumDescriptorProto {
+            name: Some(name.to_string()),
+            ..EnumDescriptorProto::default()
+        }
+    }
+
+    fn service(name: &str, methods: &[&str]) -> ServiceDescriptorProto {
+        ServiceDescriptorProto {
+            name: Some(name.to_string()),
+            method: methods.iter()
+                .map(|method| MethodDescriptorProto {
+                    name: Some(method.to_string()),
+                    ..MethodDescriptorProto::default()
+                })
+                .collect(),
+            ..ServiceDescriptorProto::default()
+        }
+    }
+
+    /// `app.proto` imports `shop.proto`, which imports `common.proto`.
+    /// `app.proto` also imports `missing.proto`, which is not registered.
+    fn index() -> Index {
+        let common = file("common.proto", "common", &[]);
+
+        let mut shop = file("shop.proto", "shop", &["common.proto"]);
+        let mut order = message("Order");
+        order.nested_type.push(message("Item"));
+        order.enum_type.push(enumeration("State"));
+        shop.message_type.push(order);
+        shop.enum_type.push(enumeration("Currency"));
+        shop.service.push(service("Shop", &["Buy"]));
+
+        let mut app = file("app.proto", "app", &["shop.proto", "missing.proto"]);
+        app.service.push(service("App", &["Run"]));
+
+        Index::new(&[app, shop, common])
+    }
+
+    fn respond(index: &Index, request: Option<MessageRequest>) -> MessageResponse {
+        let response = index.respond(ServerReflectionRequest {
+            host: "localhost".to_string(),
+            message_request: request,
+        });
+
+        assert_eq!(response.valid_host, "localhost");
+        response.message_response.expect("message_response")
+    }
+
+    /// The names of the files in a `FileDescriptorResponse`, in order.
+    fn file_names(response: MessageResponse) -> Vec<String> {
+        match response {
+            MessageResponse::FileDescriptorResponse(response) => {
+                response.file_descriptor_proto.iter()
+                    .map(|encoded| {
+                        let file = FileDescriptorProto::decode(&encoded[..]).expect("decode");
+                        name(&file.name).to_string()
+                    })
+                    .collect()
+            }
+            _ => panic!("expected a file descriptor response"),
+        }
+    }
+
+    fn error_code(response: MessageResponse) -> Code {
+        match response {
+            MessageResponse::ErrorResponse(response) => Code::from_i32(response.error_code),
+            _ => panic!("expected an error response"),
+        }
+    }
+
+    #[test]
+    fn list_services() {
+        let response = respond(&index(), Some(MessageRequest::ListServices(String::new())));
+
+        match response {
+            MessageResponse::ListServicesResponse(response) => {
+                let names = response.service.into_iter()
+                    .map(|service| service.name)
+                    .collect::<Vec<_>>();
+                assert_eq!(names, vec!["app.App", "shop.Shop"]);
+            }
+            _ => panic!("expected a list services response"),
+        }
+    }
+
+    #[test]
+    fn file_by_filename_includes_transitive_imports() {
+        let index = index();
+
+        let response = respond(&index, Some(MessageRequest::FileByFilename("app.proto".into())));
+        assert_eq!(file_names(response), vec!["app.proto", "shop.proto", "common.proto"]);
+
+        let response = respond(&index, Some(MessageRequest::FileByFilename("common.proto".into())));
+        assert_eq!(file_names(response), vec!["common.proto"]);
+    }
+
+    #[test]
+    fn file_containing_symbol() {
+        let index = index();
+
+        let symbols = [
+            "shop.Shop",
+            "shop.Shop.Buy",
+            "shop.Order",
+            "shop.Order.Item",
+            "shop.Order.State",
+            "shop.Currency",
+        ];
+
+        for &symbol in symbols.iter() {
+            let request = MessageRequest::FileContainingSymbol(symbol.to_string());
+            let response = respond(&index, Some(request));
+            assert_eq!(file_names(response), vec!["shop.proto", "common.proto"], "{}", symbol);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let index = index();
+
+        let cases = vec![
+            (Some(MessageRequest::FileByFilename("missing.proto".into())), Code::NOT_FOUND),
+            (Some(MessageRequest::FileContainingSymbol("shop.Item".into())), Code::NOT_FOUND),
+            (Some(MessageRequest::AllExtensionNumbersOfType("shop.Order".into())), Code::UNIMPLEMENTED),
+            (None, Code::INVALID_ARGUMENT),
+        ];
+
+        for (request, code) in cases {
+            assert_eq!(error_code(respond(&index, request)), code);
+        }
+    }
+}
@@ -1,8 +1,20 @@
+use cancel::{CancelHandle, Canceled};
+use deadline::{self, Deadline};
+use generic::compression::MessageCompression;
+use metadata::MetadataMap;
+
 use http;
 
+use std::time::{Duration, Instant};
+
+/// The path of the method a server request was sent to.
+#[derive(Debug, Clone)]
+struct MethodPath(http::uri::PathAndQuery);
+
 #[derive(Debug)]
 pub struct Request<T> {
-    headers: http::HeaderMap,
+    metadata: MetadataMap,
+    extensions: http::Extensions,
     message: T,
 }
 
@@ -10,7 +22,8 @@ impl<T> Request<T> {
     /// Create a new gRPC request
     pub fn new(message: T) -> Self {
         Request {
-            headers: http::HeaderMap::new(),
+            metadata: MetadataMap::new(),
+            extensions: http::Extensions::new(),
             message,
         }
     }
@@ -27,12 +40,75 @@ impl<T> Request<T> {
 
     /// Get a reference to the request headers.
     pub fn headers(&self) -> &http::HeaderMap {
-        &self.headers
+        self.metadata.headers()
     }
 
     /// Get a mutable reference to the request headers.
     pub fn headers_mut(&mut self) -> &mut http::HeaderMap {
-        &mut self.headers
+        self.metadata.headers_mut()
+    }
+
+    /// Get a reference to the request metadata.
+    pub fn metadata(&self) -> &MetadataMap {
+        &self.metadata
+    }
+
+    /// Get a mutable reference to the request metadata.
+    pub fn metadata_mut(&mut self) -> &mut MetadataMap {
+        &mut self.metadata
+    }
+
+    /// Set the time the client is willing to wait for the call to complete.
+    ///
+    /// The deadline starts when this is called. Once it passes, the call
+    /// fails with `DEADLINE_EXCEEDED` and its HTTP/2 stream is reset.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.extensions.insert(Deadline(Instant::now() + timeout));
+    }
+
+    /// The deadline of the call, if any.
+    ///
+    /// On the server, this is derived from the `grpc-timeout` header sent by
+    /// the client.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.extensions.get::<Deadline>().map(|deadline| deadline.0)
+    }
+
+    /// Get a handle for canceling the call, from any task.
+    ///
+    /// Every call returns a handle to the same call.
+    pub fn cancel_handle(&mut self) -> CancelHandle {
+        if let Some(handle) = self.extensions.get::<CancelHandle>() {
+            return handle.clone();
+        }
+
+        let handle = CancelHandle::new();
+        self.extensions.insert(handle.clone());
+        handle
+    }
+
+    /// Returns a future that resolves once the call is canceled or its
+    /// deadline passes.
+    ///
+    /// On the server, a call is canceled when the client resets its stream
+    /// or the connection is lost, so a handler can stop work whose result
+    /// will not be received.
+    pub fn canceled(&self) -> Canceled {
+        Canceled::new(self.cancel(), self.deadline())
+    }
+
+    /// The path of the method called, such as `/helloworld.Greeter/SayHello`.
+    ///
+    /// Only set on requests received by a server.
+    pub fn method_path(&self) -> Option<&http::uri::PathAndQuery> {
+        self.extensions.get::<MethodPath>().map(|path| &path.0)
+    }
+
+    /// Control compression of the request messages with `handle`.
+    ///
+    /// Has no effect unless the client is configured to compress requests.
+    pub fn set_message_compression(&mut self, handle: MessageCompression) {
+        self.extensions.insert(handle);
     }
 
     /// Consumes `self`, returning the message
@@ -43,8 +119,19 @@ impl<T> Request<T> {
     /// Convert an HTTP request to a gRPC request
     pub fn from_http(http: http::Request<T>) -> Self {
         let (head, message) = http.into_parts();
+        let mut extensions = head.extensions;
+
+        if let Some(deadline) = deadline::from_headers(&head.headers) {
+            extensions.insert(Deadline(deadline));
+        }
+
+        if let Some(path) = head.uri.path_and_query() {
+            extensions.insert(MethodPath(path.clone()));
+        }
+
         Request {
-            headers: head.headers,
+            metadata: MetadataMap::from_headers(head.headers),
+            extensions,
             message,
         }
     }
@@ -55,7 +142,8 @@ impl<T> Request<T> {
         *request.version_mut() = http::Version::HTTP_2;
         *request.method_mut() = http::Method::POST;
         *request.uri_mut() = uri;
-        *request.headers_mut() = self.headers;
+        *request.headers_mut() = self.metadata.into_headers();
+        *request.extensions_mut() = self.extensions;
 
         request
     }
@@ -66,8 +154,17 @@ impl<T> Request<T> {
         let message = f(self.message);
 
         Request {
-            headers: self.headers,
+            metadata: self.metadata,
+            extensions: self.extensions,
             message,
         }
     }
+
+    pub(crate) fn cancel(&self) -> Option<CancelHandle> {
+        self.extensions.get::<CancelHandle>().cloned()
+    }
+
+    pub(crate) fn message_compression(&self) -> Option<MessageCompression> {
+        self.extensions.get::<MessageCompression>().cloned()
+    }
 }
@@ -1,7 +1,15 @@
+use generic::compression::MessageCompression;
+use metadata::{MetadataMap, TrailingMetadata};
+
 use http;
 
+use std::mem;
+
 #[derive(Debug)]
 pub struct Response<T> {
+    metadata: MetadataMap,
+
+    /// The HTTP response, with its headers moved to `metadata`.
     http: http::Response<T>,
 }
 
@@ -11,6 +19,7 @@ impl<T> Response<T> {
         *res.version_mut() = http::Version::HTTP_2;
 
         Response {
+            metadata: MetadataMap::new(),
             http: res,
         }
     }
@@ -25,20 +34,57 @@ impl<T> Response<T> {
         self.http.body_mut()
     }
 
+    /// Get a reference to the response metadata.
+    pub fn metadata(&self) -> &MetadataMap {
+        &self.metadata
+    }
+
+    /// Get a mutable reference to the response metadata.
+    pub fn metadata_mut(&mut self) -> &mut MetadataMap {
+        &mut self.metadata
+    }
+
+    /// Get a handle for setting the trailing metadata of the response.
+    ///
+    /// Every call returns a handle to the same trailing metadata.
+    pub fn trailing_metadata(&mut self) -> TrailingMetadata {
+        let extensions = self.http.extensions_mut();
+
+        if let Some(trailers) = extensions.get::<TrailingMetadata>() {
+            return trailers.clone();
+        }
+
+        let trailers = TrailingMetadata::new();
+        extensions.insert(trailers.clone());
+        trailers
+    }
+
+    /// Control compression of the response messages with `handle`.
+    ///
+    /// Has no effect unless the server is configured to compress responses.
+    pub fn set_message_compression(&mut self, handle: MessageCompression) {
+        self.http.extensions_mut().insert(handle);
+    }
+
     /// Consumes `self`, returning the message
     pub fn into_inner(self) -> T {
         let (_, body) = self.http.into_parts();
         body
     }
 
-    pub(crate) fn from_http(res: http::Response<T>) -> Self {
+    pub(crate) fn from_http(mut res: http::Response<T>) -> Self {
+        let headers = mem::replace(res.headers_mut(), http::HeaderMap::new());
+
         Response {
+            metadata: MetadataMap::from_headers(headers),
             http: res,
         }
     }
 
     pub fn into_http(self) -> http::Response<T> {
-        self.http
+        let mut http = self.http;
+        *http.headers_mut() = self.metadata.into_headers();
+        http
     }
 
     pub fn map<F, U>(self, f: F) -> Response<U>
@@ -46,10 +92,10 @@ impl<T> Response<T> {
     {
         let (head, body) = self.http.into_parts();
         let body = f(body);
-        let http = http::Response::from_parts(head, body);
-        Response::from_http(http)
-    }
 
-    // pub fn metadata()
-    // pub fn metadata_bin()
+        Response {
+            metadata: self.metadata,
+            http: http::Response::from_parts(head, body),
+        }
+    }
 }
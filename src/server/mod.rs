@@ -3,22 +3,111 @@ pub mod server_streaming;
 pub mod streaming;
 pub mod unary;
 
+mod router;
+
+pub use self::router::{NamedService, Router, RouterService};
+
+use {MetadataMap, Status};
 use codec::{Codec, Streaming};
+use generic::DEFAULT_MAX_DECODING_MESSAGE_SIZE;
+use generic::compression::Compression;
 use generic::server::{UnaryService, ClientStreamingService, ServerStreamingService, StreamingService};
 
 use http;
 use prost;
 use tower_h2::{Body, Data};
 
-#[derive(Debug, Clone)]
+use std::{fmt, mem};
+use std::sync::Arc;
+
+/// Protobuf gRPC server configuration.
+#[derive(Clone)]
 pub struct Grpc {
-    _p: (),
+    compression: Compression,
+    max_decoding_message_size: Option<usize>,
+    max_encoding_message_size: Option<usize>,
+    interceptor: Option<Arc<dyn Interceptor>>,
+}
+
+/// Intercepts inbound requests before they are dispatched to a handler.
+///
+/// Implemented for closures of the same signature as `intercept`.
+pub trait Interceptor: Send + Sync {
+    /// Inspect the method path and metadata of a request.
+    ///
+    /// Returning an error responds with that status without calling the
+    /// handler.
+    fn intercept(&self, path: &str, metadata: &MetadataMap) -> Result<(), Status>;
 }
 
 // ===== impl Grpc =====
 
 impl Grpc {
-    pub fn unary<T, B>(service: T,
+    pub fn new() -> Self {
+        Grpc {
+            compression: Compression::new(),
+            max_decoding_message_size: Some(DEFAULT_MAX_DECODING_MESSAGE_SIZE),
+            max_encoding_message_size: None,
+            interceptor: None,
+        }
+    }
+
+    /// Pass every request through `interceptor`, replacing any previous one.
+    pub fn set_interceptor<I>(&mut self, interceptor: I) -> &mut Self
+    where I: Interceptor + 'static,
+    {
+        self.interceptor = Some(Arc::new(interceptor));
+        self
+    }
+
+    /// Run the interceptor, if any, on an inbound request.
+    ///
+    /// Used by generated servers; not part of the public API.
+    #[doc(hidden)]
+    pub fn intercept<B>(&self, request: &mut http::Request<B>) -> Result<(), Status> {
+        let interceptor = match self.interceptor {
+            Some(ref interceptor) => interceptor,
+            None => return Ok(()),
+        };
+
+        let headers = mem::replace(request.headers_mut(), http::HeaderMap::new());
+        let metadata = MetadataMap::from_headers(headers);
+
+        let result = interceptor.intercept(request.uri().path(), &metadata);
+
+        if let Err(ref status) = result {
+            debug!("request rejected by interceptor; status={:?}", status);
+        }
+
+        *request.headers_mut() = metadata.into_headers();
+        result
+    }
+
+    /// Set the compression algorithms used for requests and responses.
+    pub fn set_compression(&mut self, compression: Compression) -> &mut Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Limit the size of request messages, in bytes.
+    ///
+    /// Larger requests fail with `RESOURCE_EXHAUSTED` before being buffered.
+    /// Defaults to 4 MiB; `None` removes the limit.
+    pub fn set_max_decoding_message_size(&mut self, limit: Option<usize>) -> &mut Self {
+        self.max_decoding_message_size = limit;
+        self
+    }
+
+    /// Limit the size of response messages, in bytes.
+    ///
+    /// Larger responses fail with `RESOURCE_EXHAUSTED`. Unlimited by default.
+    pub fn set_max_encoding_message_size(&mut self, limit: Option<usize>) -> &mut Self {
+        self.max_encoding_message_size = limit;
+        self
+    }
+
+    pub fn unary<T, B>(&self,
+                       service: T,
                        request: http::Request<B>)
         -> unary::ResponseFuture<T, B>
     where T: UnaryService,
@@ -26,14 +115,13 @@ impl Grpc {
           T::Response: prost::Message,
           B: Body<Data = Data>,
     {
-        use generic::server::Grpc;
-
-        let mut grpc = Grpc::new(Codec::new());
+        let mut grpc = self.generic();
         let inner = grpc.unary(service, request);
         unary::ResponseFuture::new(inner)
     }
 
-    pub fn client_streaming<T, R, B>(service: &mut T,
+    pub fn client_streaming<T, R, B>(&self,
+                                     service: &mut T,
                                      request: http::Request<B>)
         -> client_streaming::ResponseFuture<T>
     where T: ClientStreamingService<Request = R, RequestStream = Streaming<R, B>>,
@@ -41,14 +129,13 @@ impl Grpc {
           T::Response: prost::Message,
           B: Body<Data = Data>,
     {
-        use generic::server::Grpc;
-
-        let mut grpc = Grpc::new(Codec::new());
+        let mut grpc = self.generic();
         let inner = grpc.client_streaming(service, request);
         client_streaming::ResponseFuture::new(inner)
     }
 
-    pub fn server_streaming<T, B>(service: T,
+    pub fn server_streaming<T, B>(&self,
+                                  service: T,
                                   request: http::Request<B>)
         -> server_streaming::ResponseFuture<T, B>
     where T: ServerStreamingService,
@@ -56,14 +143,13 @@ impl Grpc {
           T::Response: prost::Message,
           B: Body<Data = Data>,
     {
-        use generic::server::Grpc;
-
-        let mut grpc = Grpc::new(Codec::new());
+        let mut grpc = self.generic();
         let inner = grpc.server_streaming(service, request);
         server_streaming::ResponseFuture::new(inner)
     }
 
-    pub fn streaming<T, R, B>(service: &mut T,
+    pub fn streaming<T, R, B>(&self,
+                              service: &mut T,
                               request: http::Request<B>)
         -> streaming::ResponseFuture<T>
     where T: StreamingService<Request = R, RequestStream = Streaming<R, B>>,
@@ -71,10 +157,47 @@ impl Grpc {
           T::Response: prost::Message,
           B: Body<Data = Data>,
     {
-        use generic::server::Grpc;
-
-        let mut grpc = Grpc::new(Codec::new());
+        let mut grpc = self.generic();
         let inner = grpc.streaming(service, request);
         streaming::ResponseFuture::new(inner)
     }
+
+    /// A generic server using the protobuf codec and this configuration.
+    fn generic<T, U>(&self) -> ::generic::server::Grpc<Codec<T, U>>
+    where T: prost::Message,
+          U: prost::Message + Default,
+    {
+        let mut grpc = ::generic::server::Grpc::new(Codec::new());
+        grpc.set_compression(self.compression.clone())
+            .set_max_decoding_message_size(self.max_decoding_message_size)
+            .set_max_encoding_message_size(self.max_encoding_message_size);
+        grpc
+    }
+}
+
+impl fmt::Debug for Grpc {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Grpc")
+            .field("compression", &self.compression)
+            .field("max_decoding_message_size", &self.max_decoding_message_size)
+            .field("max_encoding_message_size", &self.max_encoding_message_size)
+            .field("interceptor", &self.interceptor.is_some())
+            .finish()
+    }
+}
+
+impl Default for Grpc {
+    fn default() -> Self {
+        Grpc::new()
+    }
+}
+
+// ===== impl Interceptor =====
+
+impl<F> Interceptor for F
+where F: Fn(&str, &MetadataMap) -> Result<(), Status> + Send + Sync,
+{
+    fn intercept(&self, path: &str, metadata: &MetadataMap) -> Result<(), Status> {
+        self(path, metadata)
+    }
 }
@@ -0,0 +1,543 @@
+//! Serve several gRPC services from a single listener.
+
+use Status;
+use generic::server::encoding;
+
+use bytes::Bytes;
+use futures::{future, Async, Future, Poll};
+use {h2, http};
+use http::header::{self, HeaderValue};
+use tower::{NewService, Service};
+use tower_h2::{Body, BoxBody, RecvBody};
+
+use std::fmt;
+
+const GRPC_CONTENT_TYPE: &str = "application/grpc";
+
+/// A server for a single gRPC service, such as a generated `*Server`.
+pub trait NamedService {
+    /// The fully-qualified name of the service, such as `helloworld.Greeter`.
+    fn service_name() -> &'static str;
+}
+
+/// Dispatches requests to gRPC services by the `/package.Service/` prefix of
+/// their path.
+///
+/// Requests for unknown services fail with `UNIMPLEMENTED`.
+///
+/// # Message encodings
+///
+/// A service may have a route per message encoding, named by the `+suffix`
+/// of the request content type, such as `json` for `application/grpc+json`.
+/// A content type without a suffix is `proto`. Requests go to the route for
+/// their encoding, or else to the route added without one; with neither,
+/// they go to any route for the service, which rejects the content type.
+///
+/// # Load shedding
+///
+/// The readiness of a service is checked when a request is routed to it, and
+/// the request fails right away with `UNAVAILABLE` if the service is not
+/// ready, rather than waiting for it. This sheds load instead of queueing it:
+/// a busy service does not hold up requests for the others on the
+/// connection, and clients may retry `UNAVAILABLE` calls. A service that
+/// should queue requests under backpressure, such as one behind a buffer,
+/// must report itself ready while its queue has room.
+///
+/// ```ignore
+/// let mut router = Router::new();
+/// router
+///     .add_service(GreeterServer::new(Greet))
+///     .add_service(RouteGuideServer::new(Guide))
+///     .add_encoded_service("json", JsonRouteGuideServer::new(Guide));
+///
+/// let h2 = tower_h2::Server::new(router, Default::default(), executor);
+/// ```
+pub struct Router {
+    routes: Vec<Route<BoxNewService>>,
+}
+
+/// The `Service` built by a `Router` for each connection.
+pub struct RouterService {
+    routes: Vec<Route<BoxService>>,
+}
+
+/// A service and the requests routed to it.
+struct Route<T> {
+    /// The `/package.Service/` prefix of the request paths.
+    prefix: String,
+
+    /// The message encoding of the requests, or `None` for any.
+    encoding: Option<String>,
+
+    service: T,
+}
+
+/// The body of a response for an unknown service.
+#[derive(Debug)]
+struct StatusBody {
+    status: Status,
+}
+
+/// Boxes a service, its responses and the services it builds.
+struct Boxed<T>(T);
+
+type BoxFuture<T> = Box<dyn Future<Item = T, Error = h2::Error> + Send>;
+
+type BoxService = Box<dyn Service<
+    Request = http::Request<RecvBody>,
+    Response = http::Response<BoxBody>,
+    Error = h2::Error,
+    Future = BoxFuture<http::Response<BoxBody>>> + Send>;
+
+type BoxNewService = Box<dyn NewService<
+    Request = http::Request<RecvBody>,
+    Response = http::Response<BoxBody>,
+    Error = h2::Error,
+    Service = BoxService,
+    InitError = h2::Error,
+    Future = BoxFuture<BoxService>> + Send + Sync>;
+
+// ===== impl Router =====
+
+impl Router {
+    /// Create a router with no services.
+    pub fn new() -> Self {
+        Router {
+            routes: Vec::new(),
+        }
+    }
+
+    /// Route requests for the service's `NamedService::service_name`.
+    pub fn add_service<S, B>(&mut self, service: S) -> &mut Self
+    where S: NamedService + NewService<Request = http::Request<RecvBody>,
+                                      Response = http::Response<B>,
+                                      Error = h2::Error,
+                                      InitError = h2::Error> + Send + Sync + 'static,
+          S::Service: Send + 'static,
+          S::Future: Send + 'static,
+          <S::Service as Service>::Future: Send + 'static,
+          B: Body<Data = Bytes> + Send + 'static,
+    {
+        self.route(S::service_name(), service)
+    }
+
+    /// Route requests for the service's `NamedService::service_name` whose
+    /// messages are in `encoding`, such as `json`.
+    pub fn add_encoded_service<S, B>(&mut self, encoding: &str, service: S) -> &mut Self
+    where S: NamedService + NewService<Request = http::Request<RecvBody>,
+                                      Response = http::Response<B>,
+                                      Error = h2::Error,
+                                      InitError = h2::Error> + Send + Sync + 'static,
+          S::Service: Send + 'static,
+          S::Future: Send + 'static,
+          <S::Service as Service>::Future: Send + 'static,
+          B: Body<Data = Bytes> + Send + 'static,
+    {
+        self.route_encoded(S::service_name(), encoding, service)
+    }
+
+    /// Route requests for the fully-qualified service `name` to `service`.
+    ///
+    /// A later route for the same name replaces the earlier one.
+    pub fn route<S, B>(&mut self, name: &str, service: S) -> &mut Self
+    where S: NewService<Request = http::Request<RecvBody>,
+                        Response = http::Response<B>,
+                        Error = h2::Error,
+                        InitError = h2::Error> + Send + Sync + 'static,
+          S::Service: Send + 'static,
+          S::Future: Send + 'static,
+          <S::Service as Service>::Future: Send + 'static,
+          B: Body<Data = Bytes> + Send + 'static,
+    {
+        self.add_route(name, None, Box::new(Boxed(service)))
+    }
+
+    /// Route requests for the fully-qualified service `name` whose messages
+    /// are in `encoding`, such as `json`, to `service`.
+    ///
+    /// A later route for the same name and encoding replaces the earlier
+    /// one.
+    pub fn route_encoded<S, B>(&mut self, name: &str, encoding: &str, service: S) -> &mut Self
+    where S: NewService<Request = http::Request<RecvBody>,
+                        Response = http::Response<B>,
+                        Error = h2::Error,
+                        InitError = h2::Error> + Send + Sync + 'static,
+          S::Service: Send + 'static,
+          S::Future: Send + 'static,
+          <S::Service as Service>::Future: Send + 'static,
+          B: Body<Data = Bytes> + Send + 'static,
+    {
+        self.add_route(name, Some(encoding.to_string()), Box::new(Boxed(service)))
+    }
+
+    fn add_route(&mut self,
+                 name: &str,
+                 encoding: Option<String>,
+                 service: BoxNewService)
+        -> &mut Self
+    {
+        let prefix = format!("/{}/", name);
+
+        self.routes.retain(|route| route.prefix != prefix || route.encoding != encoding);
+        self.routes.push(Route { prefix, encoding, service });
+        self
+    }
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Router::new()
+    }
+}
+
+impl NewService for Router {
+    type Request = http::Request<RecvBody>;
+    type Response = http::Response<BoxBody>;
+    type Error = h2::Error;
+    type Service = RouterService;
+    type InitError = h2::Error;
+    type Future = BoxFuture<RouterService>;
+
+    fn new_service(&self) -> Self::Future {
+        let keys: Vec<_> = self.routes.iter()
+            .map(|route| (route.prefix.clone(), route.encoding.clone()))
+            .collect();
+
+        let services = self.routes.iter()
+            .map(|route| route.service.new_service());
+
+        Box::new(future::join_all(services).map(move |services| {
+            let routes = keys.into_iter().zip(services)
+                .map(|((prefix, encoding), service)| Route { prefix, encoding, service })
+                .collect();
+
+            RouterService { routes }
+        }))
+    }
+}
+
+impl fmt::Debug for Router {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Router")
+            .field("routes", &Routes(&self.routes))
+            .finish()
+    }
+}
+
+// ===== impl RouterService =====
+
+impl Service for RouterService {
+    type Request = http::Request<RecvBody>;
+    type Response = http::Response<BoxBody>;
+    type Error = h2::Error;
+    type Future = BoxFuture<Self::Response>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        // The readiness of a route is checked once a request is routed to
+        // it, so a busy service does not hold up requests for the others.
+        Ok(Async::Ready(()))
+    }
+
+    fn call(&mut self, request: Self::Request) -> Self::Future {
+        let service = match select(&self.routes, &request) {
+            Some(index) => &mut self.routes[index].service,
+            None => {
+                debug!("unknown gRPC service; path={}", request.uri().path());
+                return status_response(Status::UNIMPLEMENTED);
+            }
+        };
+
+        // Not waiting for the service also means the connection task is
+        // registered for a wakeup it does not need; that only costs a
+        // spurious poll.
+        match service.poll_ready() {
+            Ok(Async::Ready(())) => service.call(request),
+            Ok(Async::NotReady) => {
+                debug!("gRPC service not ready; path={}", request.uri().path());
+                status_response(Status::UNAVAILABLE.with_message("service not ready"))
+            }
+            Err(e) => Box::new(future::err(e)),
+        }
+    }
+}
+
+impl fmt::Debug for RouterService {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("RouterService")
+            .field("routes", &Routes(&self.routes))
+            .finish()
+    }
+}
+
+// ===== impl StatusBody =====
+
+impl Body for StatusBody {
+    type Data = Bytes;
+
+    fn is_end_stream(&self) -> bool {
+        false
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, h2::Error> {
+        Ok(Async::Ready(None))
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, h2::Error> {
+        let mut map = http::HeaderMap::new();
+        self.status.add_header(&mut map);
+        Ok(Some(map).into())
+    }
+}
+
+// ===== impl Boxed =====
+
+impl<S, B> NewService for Boxed<S>
+where S: NewService<Request = http::Request<RecvBody>,
+                    Response = http::Response<B>,
+                    Error = h2::Error,
+                    InitError = h2::Error>,
+      S::Service: Send + 'static,
+      S::Future: Send + 'static,
+      <S::Service as Service>::Future: Send + 'static,
+      B: Body<Data = Bytes> + Send + 'static,
+{
+    type Request = http::Request<RecvBody>;
+    type Response = http::Response<BoxBody>;
+    type Error = h2::Error;
+    type Service = BoxService;
+    type InitError = h2::Error;
+    type Future = BoxFuture<BoxService>;
+
+    fn new_service(&self) -> Self::Future {
+        Box::new(self.0.new_service().map(|service| {
+            Box::new(Boxed(service)) as BoxService
+        }))
+    }
+}
+
+impl<S, B> Service for Boxed<S>
+where S: Service<Request = http::Request<RecvBody>,
+                 Response = http::Response<B>,
+                 Error = h2::Error>,
+      S::Future: Send + 'static,
+      B: Body<Data = Bytes> + Send + 'static,
+{
+    type Request = http::Request<RecvBody>;
+    type Response = http::Response<BoxBody>;
+    type Error = h2::Error;
+    type Future = BoxFuture<Self::Response>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.0.poll_ready()
+    }
+
+    fn call(&mut self, request: Self::Request) -> Self::Future {
+        Box::new(self.0.call(request).map(|response| {
+            response.map(|body| BoxBody::new(Box::new(body)))
+        }))
+    }
+}
+
+// ===== utility fns =====
+
+/// Returns the index of the route for `request`, if its service has one.
+fn select<T>(routes: &[Route<T>], request: &http::Request<RecvBody>) -> Option<usize> {
+    let path = request.uri().path();
+
+    let requested = request.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(encoding)
+        .map(|e| if e.is_empty() { "proto" } else { e });
+
+    let mut fallback = None;
+
+    for (index, route) in routes.iter().enumerate() {
+        if !path.starts_with(&route.prefix[..]) {
+            continue;
+        }
+
+        match route.encoding {
+            Some(ref e) if Some(&e[..]) == requested => return Some(index),
+            // Serves any encoding.
+            None => fallback = Some(index),
+            // Rejects the encoding, if nothing else handles it.
+            Some(_) => fallback = fallback.or(Some(index)),
+        }
+    }
+
+    fallback
+}
+
+/// A trailers-only response with `status`.
+fn status_response(status: Status) -> BoxFuture<http::Response<BoxBody>> {
+    let body = StatusBody { status };
+    let mut response = ::Response::new(BoxBody::new(Box::new(body))).into_http();
+
+    response.headers_mut()
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(GRPC_CONTENT_TYPE));
+
+    Box::new(future::ok(response))
+}
+
+/// Formats the route prefixes and encodings of a router.
+struct Routes<'a, T: 'a>(&'a [Route<T>]);
+
+impl<'a, T> fmt::Debug for Routes<'a, T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_list()
+            .entries(self.0.iter().map(|route| (&route.prefix, &route.encoding)))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Code;
+    use test_util::TestBody;
+
+    /// Responds with its name in the `x-service` header.
+    #[derive(Debug, Clone)]
+    struct Named {
+        name: &'static str,
+        ready: bool,
+    }
+
+    impl Service for Named {
+        type Request = http::Request<RecvBody>;
+        type Response = http::Response<TestBody>;
+        type Error = h2::Error;
+        type Future = future::FutureResult<Self::Response, h2::Error>;
+
+        fn poll_ready(&mut self) -> Poll<(), h2::Error> {
+            if self.ready {
+                Ok(Async::Ready(()))
+            } else {
+                Ok(Async::NotReady)
+            }
+        }
+
+        fn call(&mut self, _: Self::Request) -> Self::Future {
+            let response = http::Response::builder()
+                .header("x-service", self.name)
+                .body(TestBody::default())
+                .unwrap();
+            future::ok(response)
+        }
+    }
+
+    impl NewService for Named {
+        type Request = http::Request<RecvBody>;
+        type Response = http::Response<TestBody>;
+        type Error = h2::Error;
+        type Service = Named;
+        type InitError = h2::Error;
+        type Future = future::FutureResult<Named, h2::Error>;
+
+        fn new_service(&self) -> Self::Future {
+            future::ok(self.clone())
+        }
+    }
+
+    fn router_service() -> RouterService {
+        let mut router = Router::new();
+        router
+            .route("a.A", Named { name: "a", ready: true })
+            .route("b.B", Named { name: "b", ready: true })
+            .route("busy.Busy", Named { name: "busy", ready: false });
+
+        router.new_service().wait().expect("new_service")
+    }
+
+    fn call(service: &mut RouterService, path: &str) -> http::Response<BoxBody> {
+        call_with_content_type(service, path, "application/grpc")
+    }
+
+    fn call_with_content_type(service: &mut RouterService,
+                              path: &str,
+                              content_type: &str)
+        -> http::Response<BoxBody>
+    {
+        let request = http::Request::builder()
+            .uri(path)
+            .header(header::CONTENT_TYPE, content_type)
+            .body(RecvBody::default())
+            .unwrap();
+
+        service.call(request).wait().expect("call")
+    }
+
+    fn trailers_status(response: &mut http::Response<BoxBody>) -> Status {
+        match response.body_mut().poll_trailers().expect("poll_trailers") {
+            Async::Ready(Some(trailers)) => {
+                Status::from_header_map(&trailers).expect("trailers missing grpc-status")
+            }
+            trailers => panic!("unexpected trailers: {:?}", trailers),
+        }
+    }
+
+    #[test]
+    fn routes_by_service_name() {
+        let mut service = router_service();
+        assert!(service.poll_ready().unwrap().is_ready());
+
+        assert_eq!(call(&mut service, "/a.A/Call").headers()["x-service"], "a");
+        assert_eq!(call(&mut service, "/b.B/Call").headers()["x-service"], "b");
+    }
+
+    #[test]
+    fn unknown_service_is_unimplemented() {
+        let mut service = router_service();
+
+        for &path in &["/c.C/Call", "/a.AA/Call", "/"] {
+            let mut response = call(&mut service, path);
+            assert_eq!(response.headers()[header::CONTENT_TYPE], GRPC_CONTENT_TYPE);
+            assert_eq!(trailers_status(&mut response).code(), Code::UNIMPLEMENTED, "path={}", path);
+        }
+    }
+
+    #[test]
+    fn service_not_ready_is_unavailable() {
+        let mut service = router_service();
+
+        // The busy route does not hold up the others.
+        assert!(service.poll_ready().unwrap().is_ready());
+
+        let mut response = call(&mut service, "/busy.Busy/Call");
+        assert_eq!(response.headers()[header::CONTENT_TYPE], GRPC_CONTENT_TYPE);
+        assert_eq!(trailers_status(&mut response).code(), Code::UNAVAILABLE);
+    }
+
+    #[test]
+    fn routes_by_message_encoding() {
+        let mut router = Router::new();
+        router
+            .route("a.A", Named { name: "a", ready: true })
+            .route_encoded("a.A", "json", Named { name: "a+json", ready: true })
+            .route_encoded("b.B", "json", Named { name: "b+json", ready: true })
+            .route_encoded("b.B", "proto", Named { name: "b+proto", ready: true });
+
+        let mut service = router.new_service().wait().expect("new_service");
+
+        let cases = [
+            ("/a.A/Call", "application/grpc", "a"),
+            ("/a.A/Call", "application/grpc+proto", "a"),
+            ("/a.A/Call", "application/grpc+json", "a+json"),
+            ("/a.A/Call", "application/grpc+json; charset=utf-8", "a+json"),
+            ("/a.A/Call", "application/grpc+cbor", "a"),
+            ("/b.B/Call", "application/grpc", "b+proto"),
+            ("/b.B/Call", "application/grpc+json", "b+json"),
+        ];
+
+        for &(path, content_type, name) in cases.iter() {
+            let response = call_with_content_type(&mut service, path, content_type);
+            assert_eq!(response.headers()["x-service"], name, "content-type={}", content_type);
+        }
+
+        // Without a route for the encoding, a route for the service rejects
+        // the content type.
+        let response = call_with_content_type(&mut service, "/b.B/Call", "application/grpc+cbor");
+        assert!(response.headers()["x-service"].to_str().unwrap().starts_with("b+"));
+    }
+}
@@ -1,123 +1,256 @@
+use std::error::Error;
 use std::fmt;
+use std::fmt::Write;
+use std::str::FromStr;
 
+use base64;
+use bytes::Bytes;
 use h2;
+use http::{HeaderMap, StatusCode};
 use http::header::HeaderValue;
 
+const GRPC_STATUS_HEADER_CODE: &str = "grpc-status";
+const GRPC_STATUS_MESSAGE_HEADER: &str = "grpc-message";
+const GRPC_STATUS_DETAILS_HEADER: &str = "grpc-status-details-bin";
+
 #[derive(Debug, Clone)]
 pub struct Status {
+    /// The gRPC status code, sent as `grpc-status`.
     code: Code,
+
+    /// A human-readable description of the error, sent as `grpc-message`.
+    message: Option<String>,
+
+    /// Opaque binary error details, sent as `grpc-status-details-bin`.
+    details: Option<Bytes>,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Code(Code_);
 
+/// An error returned when parsing a `Code` from a string fails.
+#[derive(Debug)]
+pub struct ParseCodeError {
+    _p: (),
+}
+
 impl Status {
     #[inline]
     pub fn code(&self) -> Code {
         self.code
     }
 
+    /// Returns the message describing the error, or an empty string if
+    /// no message was provided.
+    pub fn message(&self) -> &str {
+        self.message.as_ref()
+            .map(|message| &message[..])
+            .unwrap_or("")
+    }
+
+    /// Returns the binary error details, or an empty slice if no details
+    /// were provided.
+    pub fn details(&self) -> &[u8] {
+        self.details.as_ref()
+            .map(|details| &details[..])
+            .unwrap_or(&[])
+    }
+
+    /// Returns a copy of this status with the given human-readable message.
+    ///
+    /// The message is sent to the peer in the `grpc-message` trailer.
+    pub fn with_message<T>(self, message: T) -> Status
+    where T: Into<String>,
+    {
+        Status {
+            message: Some(message.into()),
+            ..self
+        }
+    }
+
+    /// Returns a copy of this status with the given binary error details.
+    ///
+    /// The details are sent to the peer in the `grpc-status-details-bin`
+    /// trailer.
+    pub fn with_details(self, details: Bytes) -> Status {
+        Status {
+            details: Some(details),
+            ..self
+        }
+    }
+
     pub const OK: Status = Status {
         code: Code(Code_::Ok),
+        message: None,
+        details: None,
     };
 
     pub const CANCELED: Status = Status {
         code: Code(Code_::Canceled),
+        message: None,
+        details: None,
     };
 
     pub const UNKNOWN: Status = Status {
         code: Code(Code_::Unknown),
+        message: None,
+        details: None,
     };
 
     pub const INVALID_ARGUMENT: Status = Status {
         code: Code(Code_::InvalidArgument),
+        message: None,
+        details: None,
     };
 
     pub const DEADLINE_EXCEEDED: Status = Status {
         code: Code(Code_::DeadlineExceeded),
+        message: None,
+        details: None,
     };
 
     pub const NOT_FOUND: Status = Status {
         code: Code(Code_::NotFound),
+        message: None,
+        details: None,
     };
 
     pub const ALREADY_EXISTS: Status = Status {
         code: Code(Code_::AlreadyExists),
+        message: None,
+        details: None,
     };
 
     pub const PERMISSION_DENIED: Status = Status {
         code: Code(Code_::PermissionDenied),
+        message: None,
+        details: None,
     };
 
     pub const RESOURCE_EXHAUSTED: Status = Status {
         code: Code(Code_::ResourceExhausted),
+        message: None,
+        details: None,
     };
 
     pub const FAILED_PRECONDITION: Status = Status {
         code: Code(Code_::FailedPrecondition),
+        message: None,
+        details: None,
     };
 
     pub const ABORTED: Status = Status {
         code: Code(Code_::Aborted),
+        message: None,
+        details: None,
     };
 
     pub const OUT_OF_RANGE: Status = Status {
         code: Code(Code_::OutOfRange),
+        message: None,
+        details: None,
     };
 
     pub const UNIMPLEMENTED: Status = Status {
         code: Code(Code_::Unimplemented),
+        message: None,
+        details: None,
     };
 
     pub const INTERNAL: Status = Status {
         code: Code(Code_::Internal),
+        message: None,
+        details: None,
     };
 
     pub const UNAVAILABLE: Status = Status {
         code: Code(Code_::Unavailable),
+        message: None,
+        details: None,
     };
 
     pub const DATA_LOSS: Status = Status {
         code: Code(Code_::DataLoss),
+        message: None,
+        details: None,
     };
 
     pub const UNAUTHENTICATED: Status = Status {
         code: Code(Code_::Unauthenticated),
+        message: None,
+        details: None,
     };
 
     pub(crate) fn from_bytes(bytes: &[u8]) -> Status {
-        let code = match bytes.len() {
-            1 => {
-                match bytes[0] {
-                    b'0' => Code_::Ok,
-                    b'1' => Code_::Canceled,
-                    b'2' => Code_::Unknown,
-                    b'3' => Code_::InvalidArgument,
-                    b'4' => Code_::DeadlineExceeded,
-                    b'5' => Code_::NotFound,
-                    b'6' => Code_::AlreadyExists,
-                    b'7' => Code_::PermissionDenied,
-                    b'8' => Code_::ResourceExhausted,
-                    b'9' => Code_::FailedPrecondition,
-                    _ => return Status::parse_err(),
-                }
-            },
-            2 => {
-                match (bytes[0], bytes[1]) {
-                    (b'1', b'0') => Code_::Aborted,
-                    (b'1', b'1') => Code_::OutOfRange,
-                    (b'1', b'2') => Code_::Unimplemented,
-                    (b'1', b'3') => Code_::Internal,
-                    (b'1', b'4') => Code_::Unavailable,
-                    (b'1', b'5') => Code_::DataLoss,
-                    (b'1', b'6') => Code_::Unauthenticated,
-                    _ => return Status::parse_err(),
+        if bytes.is_empty() || !bytes.iter().all(u8::is_ascii_digit) {
+            return Status::parse_err();
+        }
+
+        // Only ASCII digits remain, so this is valid UTF-8.
+        let value = ::std::str::from_utf8(bytes).unwrap();
+
+        // Values too large for an `i32` are unknown codes as well.
+        match value.parse::<i32>().ok().and_then(Code_::from_i32) {
+            Some(code) => Status::new(Code(code)),
+            None => {
+                trace!("unknown grpc-status code: {}", value);
+
+                // Keep the raw value around so it shows up in diagnostics.
+                Status::UNKNOWN
+                    .with_message(format!("unknown grpc-status code {}", value))
+            }
+        }
+    }
+
+    /// Extract a status from the `grpc-status`, `grpc-message` and
+    /// `grpc-status-details-bin` headers, if `grpc-status` is present.
+    pub(crate) fn from_header_map(header_map: &HeaderMap) -> Option<Status> {
+        header_map.get(GRPC_STATUS_HEADER_CODE).map(|code| {
+            let mut status = Status::from_bytes(code.as_ref());
+
+            if let Some(message) = header_map.get(GRPC_STATUS_MESSAGE_HEADER) {
+                let message = percent_decode(message.as_ref());
+
+                // The raw value of an unknown code is kept ahead of the
+                // peer's message.
+                status.message = Some(match status.message {
+                    Some(unknown) => format!("{}: {}", unknown, message),
+                    None => message,
+                });
+            }
+
+            if let Some(details) = header_map.get(GRPC_STATUS_DETAILS_HEADER) {
+                // Peers may omit the padding, which `decode` accepts.
+                match base64::decode(details.as_bytes()) {
+                    Ok(details) => status.details = Some(details.into()),
+                    Err(_) => trace!("error decoding grpc-status-details-bin"),
                 }
-            },
-            _ => return Status::parse_err(),
-        };
+            }
 
-        Status::new(Code(code))
+            status
+        })
+    }
+
+    /// Add the `grpc-status`, `grpc-message` and `grpc-status-details-bin`
+    /// headers describing this status to the given header map.
+    ///
+    /// Used by generated servers; not part of the public API.
+    #[doc(hidden)]
+    pub fn add_header(&self, header_map: &mut HeaderMap) {
+        header_map.insert(GRPC_STATUS_HEADER_CODE, self.to_header_value());
+
+        if let Some(ref message) = self.message {
+            // The percent-encoded message only contains visible ASCII.
+            let value = HeaderValue::from_str(&percent_encode(message))
+                .expect("percent-encoded grpc-message is a valid header value");
+            header_map.insert(GRPC_STATUS_MESSAGE_HEADER, value);
+        }
+
+        if let Some(ref details) = self.details {
+            let value = HeaderValue::from_str(&base64::encode_config(details, base64::STANDARD_NO_PAD))
+                .expect("base64 grpc-status-details-bin is a valid header value");
+            header_map.insert(GRPC_STATUS_DETAILS_HEADER, value);
+        }
     }
 
     // TODO: It would be nice for this not to be public
@@ -148,32 +281,148 @@ impl Status {
     fn new(code: Code) -> Status {
         Status {
             code,
+            message: None,
+            details: None,
         }
     }
 
+    /// Infer a status from the HTTP status of a response that did not
+    /// include `grpc-status`, such as an error returned by a proxy.
+    ///
+    /// See https://github.com/grpc/grpc/blob/master/doc/http-grpc-status-mapping.md
+    pub(crate) fn from_http_status(status: StatusCode) -> Status {
+        let code = match status.as_u16() {
+            400 => Code_::Internal,
+            401 => Code_::Unauthenticated,
+            403 => Code_::PermissionDenied,
+            404 => Code_::Unimplemented,
+            429 | 502 | 503 | 504 => Code_::Unavailable,
+            _ => Code_::Unknown,
+        };
+
+        Status::new(Code(code))
+            .with_message(format!("unexpected HTTP status code {}", status.as_u16()))
+    }
+
+    /// Map an HTTP/2 error to a status.
+    ///
+    /// See https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-HTTP2.md#errors
+    pub(crate) fn from_h2_error(err: &h2::Error) -> Status {
+        let code = match err.reason() {
+            Some(h2::Reason::REFUSED_STREAM) => Code_::Unavailable,
+            Some(h2::Reason::CANCEL) => Code_::Canceled,
+            Some(h2::Reason::ENHANCE_YOUR_CALM) => Code_::ResourceExhausted,
+            Some(h2::Reason::INADEQUATE_SECURITY) => Code_::PermissionDenied,
+            Some(_) => Code_::Internal,
+            // The connection itself failed.
+            None if err.is_io() => Code_::Unavailable,
+            None => Code_::Internal,
+        };
+
+        Status::new(Code(code))
+            .with_message(err.to_string())
+    }
+
     fn parse_err() -> Status {
         trace!("error parsing grpc-status");
         Status::UNKNOWN
     }
 }
 
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.message().is_empty() {
+            fmt::Display::fmt(&self.code, f)
+        } else {
+            write!(f, "{}: {}", self.code, self.message())
+        }
+    }
+}
+
+impl Error for Status {
+    fn description(&self) -> &str {
+        self.code.as_str()
+    }
+}
+
 impl From<h2::Error> for Status {
-    fn from(_err: h2::Error) -> Self {
-        //TODO: https://grpc.io/docs/guides/wire.html#errors
-        Status::new(Code(Code_::Internal))
+    fn from(err: h2::Error) -> Self {
+        Status::from_h2_error(&err)
     }
 }
 
 impl From<Status> for h2::Error {
-    fn from(_status: Status) -> Self {
-        // TODO: implement
-        h2::Reason::INTERNAL_ERROR.into()
+    fn from(status: Status) -> Self {
+        // The inverse of the mapping above, for resetting a stream.
+        // `UNAVAILABLE` is not sent as `REFUSED_STREAM`: that would tell the
+        // peer the call was never processed and is safe to retry.
+        let reason = match status.code.0 {
+            Code_::Ok => h2::Reason::NO_ERROR,
+            Code_::Canceled | Code_::DeadlineExceeded => h2::Reason::CANCEL,
+            Code_::ResourceExhausted => h2::Reason::ENHANCE_YOUR_CALM,
+            Code_::PermissionDenied => h2::Reason::INADEQUATE_SECURITY,
+            _ => h2::Reason::INTERNAL_ERROR,
+        };
+
+        reason.into()
     }
 }
 
 impl Code {
     pub const OK: Code = Code(Code_::Ok);
-    //TODO: the rest...
+    pub const CANCELED: Code = Code(Code_::Canceled);
+    pub const UNKNOWN: Code = Code(Code_::Unknown);
+    pub const INVALID_ARGUMENT: Code = Code(Code_::InvalidArgument);
+    pub const DEADLINE_EXCEEDED: Code = Code(Code_::DeadlineExceeded);
+    pub const NOT_FOUND: Code = Code(Code_::NotFound);
+    pub const ALREADY_EXISTS: Code = Code(Code_::AlreadyExists);
+    pub const PERMISSION_DENIED: Code = Code(Code_::PermissionDenied);
+    pub const RESOURCE_EXHAUSTED: Code = Code(Code_::ResourceExhausted);
+    pub const FAILED_PRECONDITION: Code = Code(Code_::FailedPrecondition);
+    pub const ABORTED: Code = Code(Code_::Aborted);
+    pub const OUT_OF_RANGE: Code = Code(Code_::OutOfRange);
+    pub const UNIMPLEMENTED: Code = Code(Code_::Unimplemented);
+    pub const INTERNAL: Code = Code(Code_::Internal);
+    pub const UNAVAILABLE: Code = Code(Code_::Unavailable);
+    pub const DATA_LOSS: Code = Code(Code_::DataLoss);
+    pub const UNAUTHENTICATED: Code = Code(Code_::Unauthenticated);
+
+    /// Get the code corresponding to the given numeric value.
+    ///
+    /// Values outside of the range defined by gRPC map to `Code::UNKNOWN`.
+    pub fn from_i32(value: i32) -> Code {
+        Code(Code_::from_i32(value).unwrap_or(Code_::Unknown))
+    }
+
+    /// Get the numeric value of the code, as sent in `grpc-status`.
+    pub fn to_i32(self) -> i32 {
+        self.0 as i32
+    }
+
+    /// Get the canonical name of the code, such as `NOT_FOUND`.
+    pub fn as_str(&self) -> &'static str {
+        use self::Code_::*;
+
+        match self.0 {
+            Ok => "OK",
+            Canceled => "CANCELLED",
+            Unknown => "UNKNOWN",
+            InvalidArgument => "INVALID_ARGUMENT",
+            DeadlineExceeded => "DEADLINE_EXCEEDED",
+            NotFound => "NOT_FOUND",
+            AlreadyExists => "ALREADY_EXISTS",
+            PermissionDenied => "PERMISSION_DENIED",
+            ResourceExhausted => "RESOURCE_EXHAUSTED",
+            FailedPrecondition => "FAILED_PRECONDITION",
+            Aborted => "ABORTED",
+            OutOfRange => "OUT_OF_RANGE",
+            Unimplemented => "UNIMPLEMENTED",
+            Internal => "INTERNAL",
+            Unavailable => "UNAVAILABLE",
+            DataLoss => "DATA_LOSS",
+            Unauthenticated => "UNAUTHENTICATED",
+        }
+    }
 }
 
 impl fmt::Debug for Code {
@@ -182,7 +431,112 @@ impl fmt::Debug for Code {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Code {
+    type Err = ParseCodeError;
+
+    /// Parse a code from its canonical name, such as `NOT_FOUND`.
+    fn from_str(s: &str) -> Result<Code, ParseCodeError> {
+        use self::Code_::*;
+
+        let code = match s {
+            "OK" => Ok,
+            "CANCELLED" => Canceled,
+            "UNKNOWN" => Unknown,
+            "INVALID_ARGUMENT" => InvalidArgument,
+            "DEADLINE_EXCEEDED" => DeadlineExceeded,
+            "NOT_FOUND" => NotFound,
+            "ALREADY_EXISTS" => AlreadyExists,
+            "PERMISSION_DENIED" => PermissionDenied,
+            "RESOURCE_EXHAUSTED" => ResourceExhausted,
+            "FAILED_PRECONDITION" => FailedPrecondition,
+            "ABORTED" => Aborted,
+            "OUT_OF_RANGE" => OutOfRange,
+            "UNIMPLEMENTED" => Unimplemented,
+            "INTERNAL" => Internal,
+            "UNAVAILABLE" => Unavailable,
+            "DATA_LOSS" => DataLoss,
+            "UNAUTHENTICATED" => Unauthenticated,
+            _ => return Err(ParseCodeError { _p: () }),
+        };
+
+        ::std::result::Result::Ok(Code(code))
+    }
+}
+
+// ===== impl ParseCodeError =====
+
+impl fmt::Display for ParseCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("invalid gRPC status code name")
+    }
+}
+
+impl Error for ParseCodeError {
+    fn description(&self) -> &str {
+        "invalid gRPC status code name"
+    }
+}
+
+// ===== utility fns =====
+
+/// Percent-encode a `grpc-message` value.
+///
+/// Per the gRPC wire spec, every byte outside of visible ASCII, as well as
+/// `%` itself, is sent as `%XX`.
+fn percent_encode(message: &str) -> String {
+    let mut encoded = String::with_capacity(message.len());
+
+    for &b in message.as_bytes() {
+        if b >= 0x20 && b <= 0x7E && b != b'%' {
+            encoded.push(b as char);
+        } else {
+            write!(&mut encoded, "%{:02X}", b).unwrap();
+        }
+    }
+
+    encoded
+}
+
+/// Percent-decode a `grpc-message` value.
+///
+/// Malformed escape sequences are passed through untouched rather than
+/// discarding the message, and invalid UTF-8 is replaced.
+fn percent_decode(bytes: &[u8]) -> String {
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let (Some(hi), Some(lo)) = (from_hex(bytes[i + 1]), from_hex(bytes[i + 2])) {
+                decoded.push(hi << 4 | lo);
+                i += 3;
+                continue;
+            }
+        }
+
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn from_hex(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 enum Code_ {
     Ok = 0,
     Canceled = 1,
@@ -202,3 +556,225 @@ enum Code_ {
     DataLoss = 15,
     Unauthenticated = 16,
 }
+
+impl Code_ {
+    fn from_i32(value: i32) -> Option<Code_> {
+        use self::Code_::*;
+
+        let code = match value {
+            0 => Ok,
+            1 => Canceled,
+            2 => Unknown,
+            3 => InvalidArgument,
+            4 => DeadlineExceeded,
+            5 => NotFound,
+            6 => AlreadyExists,
+            7 => PermissionDenied,
+            8 => ResourceExhausted,
+            9 => FailedPrecondition,
+            10 => Aborted,
+            11 => OutOfRange,
+            12 => Unimplemented,
+            13 => Internal,
+            14 => Unavailable,
+            15 => DataLoss,
+            16 => Unauthenticated,
+            _ => return None,
+        };
+
+        Some(code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io;
+
+    #[test]
+    fn percent_encoding_round_trips() {
+        let cases = [
+            ("hello, world", "hello, world"),
+            ("50%", "50%25"),
+            ("line\nbreak", "line%0Abreak"),
+            ("caf\u{e9} \u{2713}", "caf%C3%A9 %E2%9C%93"),
+        ];
+
+        for &(message, encoded) in cases.iter() {
+            assert_eq!(percent_encode(message), encoded);
+            assert_eq!(percent_decode(encoded.as_bytes()), message);
+        }
+    }
+
+    #[test]
+    fn percent_decoding_passes_malformed_escapes_through() {
+        assert_eq!(percent_decode(b"100%zz"), "100%zz");
+        assert_eq!(percent_decode(b"trailing %4"), "trailing %4");
+        assert_eq!(percent_decode(b"%"), "%");
+        assert_eq!(percent_decode(b"%FF"), "\u{fffd}");
+    }
+
+    #[test]
+    fn header_map_round_trips() {
+        let status = Status::INTERNAL
+            .with_message("bad \u{2713}\n")
+            .with_details(Bytes::from_static(b"\x00\x01details"));
+
+        let mut map = HeaderMap::new();
+        status.add_header(&mut map);
+
+        assert_eq!(map[GRPC_STATUS_HEADER_CODE], "13");
+        assert_eq!(map[GRPC_STATUS_MESSAGE_HEADER], "bad %E2%9C%93%0A");
+
+        let decoded = Status::from_header_map(&map).expect("grpc-status");
+        assert_eq!(decoded.code(), Code::INTERNAL);
+        assert_eq!(decoded.message(), status.message());
+        assert_eq!(decoded.details(), status.details());
+    }
+
+    #[test]
+    fn header_map_accepts_padded_details() {
+        let mut map = HeaderMap::new();
+        map.insert(GRPC_STATUS_HEADER_CODE, HeaderValue::from_static("5"));
+        map.insert(GRPC_STATUS_DETAILS_HEADER, HeaderValue::from_static("AAE="));
+
+        let status = Status::from_header_map(&map).expect("grpc-status");
+        assert_eq!(status.code(), Code::NOT_FOUND);
+        assert_eq!(status.message(), "");
+        assert_eq!(status.details(), &[0, 1]);
+    }
+
+    #[test]
+    fn header_map_keeps_unknown_codes_with_the_message() {
+        let mut map = HeaderMap::new();
+        map.insert(GRPC_STATUS_HEADER_CODE, HeaderValue::from_static("42"));
+
+        let status = Status::from_header_map(&map).expect("grpc-status");
+        assert_eq!(status.code(), Code::UNKNOWN);
+        assert_eq!(status.message(), "unknown grpc-status code 42");
+
+        map.insert(GRPC_STATUS_MESSAGE_HEADER, HeaderValue::from_static("out%20of%20cheese"));
+
+        let status = Status::from_header_map(&map).expect("grpc-status");
+        assert_eq!(status.code(), Code::UNKNOWN);
+        assert_eq!(status.message(), "unknown grpc-status code 42: out of cheese");
+    }
+
+    #[test]
+    fn status_from_bytes() {
+        assert_eq!(Status::from_bytes(b"0").code(), Code::OK);
+        assert_eq!(Status::from_bytes(b"16").code(), Code::UNAUTHENTICATED);
+        assert_eq!(Status::from_bytes(b"007").code(), Code::UNIMPLEMENTED);
+
+        // Unknown codes keep the raw value, whether or not it fits an `i32`.
+        for &raw in ["17", "99999999999"].iter() {
+            let status = Status::from_bytes(raw.as_bytes());
+            assert_eq!(status.code(), Code::UNKNOWN);
+            assert_eq!(status.message(), format!("unknown grpc-status code {}", raw));
+        }
+
+        let invalid: [&[u8]; 4] = [b"", b"-1", b"1a", b" 1"];
+
+        for &invalid in invalid.iter() {
+            let status = Status::from_bytes(invalid);
+            assert_eq!(status.code(), Code::UNKNOWN);
+            assert_eq!(status.message(), "");
+        }
+    }
+
+    #[test]
+    fn code_numeric_values() {
+        for value in 0..17 {
+            assert_eq!(Code::from_i32(value).to_i32(), value);
+        }
+
+        assert_eq!(Code::from_i32(5), Code::NOT_FOUND);
+        assert_eq!(Code::UNAVAILABLE.to_i32(), 14);
+        assert_eq!(Code::from_i32(17), Code::UNKNOWN);
+        assert_eq!(Code::from_i32(-1), Code::UNKNOWN);
+    }
+
+    #[test]
+    fn code_names() {
+        for value in 0..17 {
+            let code = Code::from_i32(value);
+            assert_eq!(code.to_string(), code.as_str());
+            assert_eq!(code.as_str().parse::<Code>().expect("parse"), code);
+        }
+
+        assert_eq!(Code::CANCELED.to_string(), "CANCELLED");
+        assert_eq!(Code::DEADLINE_EXCEEDED.to_string(), "DEADLINE_EXCEEDED");
+        assert!("CANCELED".parse::<Code>().is_err());
+        assert!("not_found".parse::<Code>().is_err());
+        assert!("".parse::<Code>().is_err());
+    }
+
+    #[test]
+    fn status_from_h2_error() {
+        let cases = [
+            (h2::Reason::REFUSED_STREAM, Code::UNAVAILABLE),
+            (h2::Reason::CANCEL, Code::CANCELED),
+            (h2::Reason::ENHANCE_YOUR_CALM, Code::RESOURCE_EXHAUSTED),
+            (h2::Reason::INADEQUATE_SECURITY, Code::PERMISSION_DENIED),
+            (h2::Reason::PROTOCOL_ERROR, Code::INTERNAL),
+            (h2::Reason::INTERNAL_ERROR, Code::INTERNAL),
+        ];
+
+        for &(reason, code) in cases.iter() {
+            assert_eq!(Status::from(h2::Error::from(reason)).code(), code, "{:?}", reason);
+        }
+
+        let io = io::Error::new(io::ErrorKind::ConnectionReset, "reset");
+        assert_eq!(Status::from(h2::Error::from(io)).code(), Code::UNAVAILABLE);
+    }
+
+    #[test]
+    fn h2_error_from_status() {
+        let cases = [
+            (Code::OK, h2::Reason::NO_ERROR),
+            (Code::CANCELED, h2::Reason::CANCEL),
+            (Code::DEADLINE_EXCEEDED, h2::Reason::CANCEL),
+            (Code::RESOURCE_EXHAUSTED, h2::Reason::ENHANCE_YOUR_CALM),
+            (Code::PERMISSION_DENIED, h2::Reason::INADEQUATE_SECURITY),
+            (Code::UNAVAILABLE, h2::Reason::INTERNAL_ERROR),
+            (Code::INTERNAL, h2::Reason::INTERNAL_ERROR),
+            (Code::UNKNOWN, h2::Reason::INTERNAL_ERROR),
+        ];
+
+        for &(code, reason) in cases.iter() {
+            let err = h2::Error::from(Status::new(code));
+            assert_eq!(err.reason(), Some(reason), "{:?}", code);
+        }
+    }
+
+    #[test]
+    fn status_from_http_status() {
+        let cases = [
+            (400, Code::INTERNAL),
+            (401, Code::UNAUTHENTICATED),
+            (403, Code::PERMISSION_DENIED),
+            (404, Code::UNIMPLEMENTED),
+            (429, Code::UNAVAILABLE),
+            (502, Code::UNAVAILABLE),
+            (503, Code::UNAVAILABLE),
+            (504, Code::UNAVAILABLE),
+            (500, Code::UNKNOWN),
+            (302, Code::UNKNOWN),
+        ];
+
+        for &(http_status, code) in cases.iter() {
+            let status = Status::from_http_status(StatusCode::from_u16(http_status).unwrap());
+            assert_eq!(status.code(), code, "{}", http_status);
+            assert_eq!(status.message(), format!("unexpected HTTP status code {}", http_status));
+        }
+    }
+
+    #[test]
+    fn header_map_without_grpc_status() {
+        let mut map = HeaderMap::new();
+        map.insert(GRPC_STATUS_MESSAGE_HEADER, HeaderValue::from_static("orphan"));
+
+        assert!(Status::from_header_map(&map).is_none());
+    }
+}
@@ -0,0 +1,110 @@
+//! Fixtures shared by unit tests.
+
+use generic::compression::Compressor;
+
+use bytes::{BigEndian, BufMut, Bytes};
+use futures::Poll;
+use h2;
+use http::HeaderMap;
+use tower_h2::Body;
+
+use std::collections::VecDeque;
+use std::io;
+
+/// A body of the given chunks, followed by trailers or an error.
+#[derive(Debug, Default)]
+pub(crate) struct TestBody {
+    chunks: VecDeque<Bytes>,
+
+    /// Returned instead of the end of the data, if set.
+    error: Option<h2::Error>,
+
+    trailers: Option<HeaderMap>,
+}
+
+/// Reverses messages, standing in for a real compression algorithm.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Reverse;
+
+// ===== impl TestBody =====
+
+impl TestBody {
+    pub(crate) fn new<I>(chunks: I) -> Self
+    where I: IntoIterator<Item = Bytes>,
+    {
+        TestBody {
+            chunks: chunks.into_iter().collect(),
+            ..TestBody::default()
+        }
+    }
+
+    /// End the data with `error`, such as a stream reset.
+    pub(crate) fn with_error<E>(mut self, error: E) -> Self
+    where E: Into<h2::Error>,
+    {
+        self.error = Some(error.into());
+        self
+    }
+
+    pub(crate) fn with_trailers(mut self, trailers: HeaderMap) -> Self {
+        self.trailers = Some(trailers);
+        self
+    }
+}
+
+impl Body for TestBody {
+    type Data = Bytes;
+
+    fn is_end_stream(&self) -> bool {
+        self.chunks.is_empty() && self.error.is_none() && self.trailers.is_none()
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Bytes>, h2::Error> {
+        if let Some(chunk) = self.chunks.pop_front() {
+            return Ok(Some(chunk).into());
+        }
+
+        match self.error.take() {
+            Some(error) => Err(error),
+            None => Ok(None.into()),
+        }
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, h2::Error> {
+        Ok(self.trailers.take().into())
+    }
+}
+
+// ===== impl Reverse =====
+
+impl Compressor for Reverse {
+    fn name(&self) -> &'static str {
+        "reverse"
+    }
+
+    fn compress(&self, src: &[u8], dst: &mut Vec<u8>) -> io::Result<()> {
+        dst.extend(src.iter().rev());
+        Ok(())
+    }
+
+    fn decompress(&self, src: &[u8], dst: &mut Vec<u8>, limit: usize) -> io::Result<()> {
+        dst.extend(src.iter().rev().take(limit));
+        Ok(())
+    }
+}
+
+// ===== utility fns =====
+
+/// Frame `message` as gRPC does, with the given compression flag.
+pub(crate) fn frame_with_flag(flag: u8, message: &[u8]) -> Bytes {
+    let mut frame = Vec::with_capacity(5 + message.len());
+    frame.put_u8(flag);
+    frame.put_u32::<BigEndian>(message.len() as u32);
+    frame.put_slice(message);
+    frame.into()
+}
+
+/// Frame an uncompressed `message`.
+pub(crate) fn frame(message: &[u8]) -> Bytes {
+    frame_with_flag(0, message)
+}
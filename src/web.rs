@@ -0,0 +1,481 @@
+//! gRPC-Web, for clients such as browsers that cannot use HTTP/2 trailers.
+//!
+//! gRPC-Web frames messages as gRPC does, but sends the trailers as the last
+//! frame of the response body, flagged `0x80`. In the text mode,
+//! `application/grpc-web-text`, bodies are also base64 encoded.
+//!
+//! `GrpcWeb` wraps a gRPC server, such as a generated `*Server`, to accept
+//! gRPC-Web requests:
+//!
+//! ```ignore
+//! let new_service = GrpcWeb::new(GreeterServer::new(Greet));
+//! ```
+//!
+//! A client speaks gRPC-Web once `client::Grpc::set_web_mode` is set.
+
+use error::ProtocolError;
+
+use base64;
+use bytes::{BufMut, BigEndian, Bytes, BytesMut};
+use futures::{Async, Future, Poll};
+use {h2, http};
+use http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use tower::{NewService, Service};
+use tower_h2::Body;
+
+/// Flags a body frame holding the trailers.
+pub(crate) const TRAILERS_FLAG: u8 = 0x80;
+
+const GRPC_CONTENT_TYPE: &str = "application/grpc";
+const WEB_CONTENT_TYPE: &str = "application/grpc-web";
+const WEB_TEXT_CONTENT_TYPE: &str = "application/grpc-web-text";
+
+/// The gRPC-Web wire format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// `application/grpc-web`, with binary bodies.
+    Binary,
+
+    /// `application/grpc-web-text`, with base64 encoded bodies.
+    Text,
+}
+
+/// Translates gRPC-Web requests for an inner gRPC service.
+///
+/// Other requests are passed through unchanged.
+#[derive(Debug, Clone)]
+pub struct GrpcWeb<S> {
+    inner: S,
+}
+
+/// Wraps the service built by an inner `NewService` in `GrpcWeb`.
+#[derive(Debug)]
+pub struct NewServiceFuture<F> {
+    inner: F,
+}
+
+/// Translates the response of the inner service to gRPC-Web.
+#[derive(Debug)]
+pub struct ResponseFuture<F> {
+    inner: F,
+    mode: Option<Mode>,
+}
+
+/// A response body with the trailers moved into a final body frame.
+#[derive(Debug)]
+pub struct ResponseBody<B> {
+    inner: B,
+    mode: Option<Mode>,
+    state: State,
+}
+
+#[derive(Debug)]
+enum State {
+    Data,
+    Trailers,
+    Done,
+}
+
+/// Decodes a base64 body received in chunks.
+#[derive(Debug, Default)]
+pub(crate) struct TextDecoder {
+    /// Received text that does not yet fill a base64 quantum.
+    buf: BytesMut,
+}
+
+// ===== impl Mode =====
+
+impl Mode {
+    /// The gRPC-Web content type for the gRPC `content_type`.
+    pub(crate) fn content_type(self, content_type: &str) -> String {
+        let subtype = if content_type.starts_with(GRPC_CONTENT_TYPE) {
+            &content_type[GRPC_CONTENT_TYPE.len()..]
+        } else {
+            "+proto"
+        };
+
+        match self {
+            Mode::Binary => format!("{}{}", WEB_CONTENT_TYPE, subtype),
+            Mode::Text => format!("{}{}", WEB_TEXT_CONTENT_TYPE, subtype),
+        }
+    }
+
+    /// Encode a chunk of a body.
+    pub(crate) fn encode(self, data: Bytes) -> Bytes {
+        match self {
+            Mode::Binary => data,
+            Mode::Text => Bytes::from(base64::encode(&data)),
+        }
+    }
+}
+
+// ===== impl GrpcWeb =====
+
+impl<S> GrpcWeb<S> {
+    /// Accept gRPC-Web requests for `inner`.
+    pub fn new(inner: S) -> Self {
+        GrpcWeb { inner }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, A, B> Service for GrpcWeb<S>
+where S: Service<Request = http::Request<A>,
+                 Response = http::Response<B>>,
+      B: Body<Data = Bytes>,
+{
+    type Request = http::Request<A>;
+    type Response = http::Response<ResponseBody<B>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut request: Self::Request) -> Self::Future {
+        let web = request.headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(grpc_content_type);
+
+        let mode = web.map(|(mode, content_type)| {
+            trace!("translating gRPC-Web request; mode={:?}", mode);
+
+            // The body is framed as gRPC expects, once decoded. The mode is
+            // left in the extensions so the server can decode it.
+            request.headers_mut().insert(header::CONTENT_TYPE, content_type);
+            request.extensions_mut().insert(mode);
+            mode
+        });
+
+        ResponseFuture {
+            inner: self.inner.call(request),
+            mode,
+        }
+    }
+}
+
+impl<S, A, B> NewService for GrpcWeb<S>
+where S: NewService<Request = http::Request<A>,
+                    Response = http::Response<B>>,
+      B: Body<Data = Bytes>,
+{
+    type Request = http::Request<A>;
+    type Response = http::Response<ResponseBody<B>>;
+    type Error = S::Error;
+    type Service = GrpcWeb<S::Service>;
+    type InitError = S::InitError;
+    type Future = NewServiceFuture<S::Future>;
+
+    fn new_service(&self) -> Self::Future {
+        NewServiceFuture {
+            inner: self.inner.new_service(),
+        }
+    }
+}
+
+// ===== impl NewServiceFuture =====
+
+impl<F> Future for NewServiceFuture<F>
+where F: Future,
+{
+    type Item = GrpcWeb<F::Item>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let inner = try_ready!(self.inner.poll());
+        Ok(GrpcWeb::new(inner).into())
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where F: Future<Item = http::Response<B>>,
+      B: Body<Data = Bytes>,
+{
+    type Item = http::Response<ResponseBody<B>>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let response = try_ready!(self.inner.poll());
+        let (mut head, inner) = response.into_parts();
+
+        if let Some(mode) = self.mode {
+            let content_type = {
+                let grpc = head.headers.get(header::CONTENT_TYPE)
+                    .and_then(|value| value.to_str().ok())
+                    .unwrap_or(GRPC_CONTENT_TYPE);
+
+                mode.content_type(grpc)
+            };
+
+            if let Ok(value) = HeaderValue::from_str(&content_type) {
+                head.headers.insert(header::CONTENT_TYPE, value);
+            }
+        }
+
+        let body = ResponseBody {
+            inner,
+            mode: self.mode,
+            state: State::Data,
+        };
+
+        Ok(http::Response::from_parts(head, body).into())
+    }
+}
+
+// ===== impl ResponseBody =====
+
+impl<B> Body for ResponseBody<B>
+where B: Body<Data = Bytes>,
+{
+    type Data = Bytes;
+
+    fn is_end_stream(&self) -> bool {
+        match (self.mode, &self.state) {
+            (None, _) => self.inner.is_end_stream(),
+            (Some(_), &State::Done) => true,
+            (Some(_), _) => false,
+        }
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, h2::Error> {
+        let mode = match self.mode {
+            Some(mode) => mode,
+            None => return self.inner.poll_data(),
+        };
+
+        loop {
+            match self.state {
+                State::Data => {
+                    if let Some(data) = try_ready!(self.inner.poll_data()) {
+                        return Ok(Some(mode.encode(data)).into());
+                    }
+
+                    self.state = State::Trailers;
+                }
+                State::Trailers => {
+                    let trailers = try_ready!(self.inner.poll_trailers());
+                    self.state = State::Done;
+
+                    if let Some(trailers) = trailers {
+                        return Ok(Some(mode.encode(encode_trailers(&trailers))).into());
+                    }
+                }
+                State::Done => return Ok(None.into()),
+            }
+        }
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, h2::Error> {
+        match self.mode {
+            // The trailers were sent in the body.
+            Some(_) => Ok(None.into()),
+            None => self.inner.poll_trailers(),
+        }
+    }
+}
+
+// ===== impl TextDecoder =====
+
+impl TextDecoder {
+    /// Decode the next chunk of text, keeping any incomplete base64 quantum
+    /// for the next chunk.
+    pub(crate) fn decode(&mut self, text: &[u8]) -> Result<Bytes, ProtocolError> {
+        self.buf.extend_from_slice(text);
+
+        let len = self.buf.len() - self.buf.len() % 4;
+        let text = self.buf.split_to(len);
+
+        let mut decoded = Vec::with_capacity(len / 4 * 3);
+        let mut start = 0;
+
+        // A peer may pad each chunk it sends, so padding can end a quantum
+        // in the middle of the body.
+        for end in (4..=len).step_by(4) {
+            if text[end - 1] == b'=' || end == len {
+                base64::decode_config_buf(&text[start..end], base64::STANDARD, &mut decoded)
+                    .map_err(|_| ProtocolError::InvalidText)?;
+                start = end;
+            }
+        }
+
+        Ok(Bytes::from(decoded))
+    }
+
+    /// Returns true if no partial quantum is buffered.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+}
+
+// ===== utility fns =====
+
+/// Returns the mode of a gRPC-Web content type, and the matching gRPC
+/// content type.
+fn grpc_content_type(content_type: &str) -> Option<(Mode, HeaderValue)> {
+    let (mode, subtype) = if content_type.starts_with(WEB_TEXT_CONTENT_TYPE) {
+        (Mode::Text, &content_type[WEB_TEXT_CONTENT_TYPE.len()..])
+    } else if content_type.starts_with(WEB_CONTENT_TYPE) {
+        (Mode::Binary, &content_type[WEB_CONTENT_TYPE.len()..])
+    } else {
+        return None;
+    };
+
+    if !subtype.is_empty() && !subtype.starts_with('+') && !subtype.starts_with(';') {
+        return None;
+    }
+
+    HeaderValue::from_str(&format!("{}{}", GRPC_CONTENT_TYPE, subtype))
+        .ok()
+        .map(|value| (mode, value))
+}
+
+/// Encode trailers as a gRPC-Web trailers frame.
+pub(crate) fn encode_trailers(trailers: &HeaderMap) -> Bytes {
+    let mut block = Vec::new();
+
+    for (name, value) in trailers.iter() {
+        block.extend_from_slice(name.as_str().as_bytes());
+        block.extend_from_slice(b":");
+        block.extend_from_slice(value.as_bytes());
+        block.extend_from_slice(b"\r\n");
+    }
+
+    let mut frame = BytesMut::with_capacity(5 + block.len());
+    frame.put_u8(TRAILERS_FLAG);
+    frame.put_u32::<BigEndian>(block.len() as u32);
+    frame.put_slice(&block);
+    frame.freeze()
+}
+
+/// Decode the payload of a gRPC-Web trailers frame.
+pub(crate) fn decode_trailers(block: &[u8]) -> Result<HeaderMap, ProtocolError> {
+    let mut trailers = HeaderMap::new();
+
+    for line in block.split(|&b| b == b'\n') {
+        let line = trim(line);
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let colon = line.iter()
+            .position(|&b| b == b':')
+            .ok_or(ProtocolError::InvalidTrailers)?;
+
+        let name = HeaderName::from_bytes(&trim(&line[..colon]).to_ascii_lowercase())
+            .map_err(|_| ProtocolError::InvalidTrailers)?;
+        let value = HeaderValue::from_bytes(trim(&line[colon + 1..]))
+            .map_err(|_| ProtocolError::InvalidTrailers)?;
+
+        trailers.append(name, value);
+    }
+
+    Ok(trailers)
+}
+
+fn trim(value: &[u8]) -> &[u8] {
+    let is_space = |b: &u8| *b == b' ' || *b == b'\t' || *b == b'\r';
+
+    let start = value.iter().position(|b| !is_space(b)).unwrap_or(value.len());
+    let end = value.iter().rposition(|b| !is_space(b)).map(|i| i + 1).unwrap_or(start);
+    &value[start..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trailers_round_trip() {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", HeaderValue::from_static("0"));
+        trailers.insert("grpc-message", HeaderValue::from_static("all good"));
+        trailers.append("x-multi", HeaderValue::from_static("a"));
+        trailers.append("x-multi", HeaderValue::from_static("b"));
+
+        let frame = encode_trailers(&trailers);
+        assert_eq!(frame[0], TRAILERS_FLAG);
+        assert_eq!(&frame[1..5], &[0, 0, 0, frame.len() as u8 - 5][..]);
+
+        assert_eq!(decode_trailers(&frame[5..]).unwrap(), trailers);
+    }
+
+    #[test]
+    fn decode_trailers_is_lenient() {
+        let trailers = decode_trailers(b"Grpc-Status: 0\r\n\nx-extra:\tvalue \n").unwrap();
+
+        assert_eq!(trailers.len(), 2);
+        assert_eq!(trailers["grpc-status"], "0");
+        assert_eq!(trailers["x-extra"], "value");
+    }
+
+    #[test]
+    fn decode_invalid_trailers() {
+        let invalid: [&[u8]; 3] = [b"grpc-status", b"bad name: 0", b"x-extra: \x7f"];
+
+        for block in &invalid {
+            match decode_trailers(block) {
+                Err(ProtocolError::InvalidTrailers) => {}
+                res => panic!("unexpected result for {:?}: {:?}", block, res),
+            }
+        }
+    }
+
+    fn decode_chunks(chunks: &[&[u8]]) -> Vec<u8> {
+        let mut decoder = TextDecoder::default();
+        let mut decoded = Vec::new();
+
+        for chunk in chunks {
+            decoded.extend_from_slice(&decoder.decode(chunk).unwrap());
+        }
+
+        assert!(decoder.is_empty());
+        decoded
+    }
+
+    #[test]
+    fn text_decoder_buffers_partial_quanta() {
+        let text = base64::encode(b"hello, world");
+
+        for split in 0..text.len() {
+            let (a, b) = text.as_bytes().split_at(split);
+            assert_eq!(decode_chunks(&[a, b]), b"hello, world", "split={}", split);
+        }
+
+        let bytes = text.as_bytes().chunks(1).collect::<Vec<_>>();
+        assert_eq!(decode_chunks(&bytes), b"hello, world");
+    }
+
+    #[test]
+    fn text_decoder_accepts_padding_mid_body() {
+        // Separately encoded chunks are each padded.
+        let text = format!("{}{}{}", base64::encode(b"a"), base64::encode(b"bc"), base64::encode(b"def"));
+        assert_eq!(text, "YQ==YmM=ZGVm");
+
+        assert_eq!(decode_chunks(&[text.as_bytes()]), b"abcdef");
+        assert_eq!(decode_chunks(&[&text.as_bytes()[..6], &text.as_bytes()[6..]]), b"abcdef");
+    }
+
+    #[test]
+    fn text_decoder_rejects_invalid_text() {
+        let mut decoder = TextDecoder::default();
+
+        match decoder.decode(b"YQ=!") {
+            Err(ProtocolError::InvalidText) => {}
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+}
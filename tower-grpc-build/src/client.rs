@@ -63,7 +63,7 @@ impl ServiceGenerator {
         let imp = scope.new_impl(&service.name)
             .generic("T")
             .target_generic("T")
-            .bound("T", "tower_h2::HttpService")
+            .bound("T", "tower_h2::HttpService<RequestBody = tower_h2::BoxBody>")
             ;
 
         imp.new_fn("new")
@@ -74,6 +74,17 @@ impl ServiceGenerator {
             .line("Self { inner }")
             ;
 
+        imp.new_fn("with_interceptor")
+            .vis("pub")
+            .generic("I")
+            .arg("inner", "T")
+            .arg("interceptor", "I")
+            .ret("Self")
+            .bound("I", "grpc::Interceptor + 'static")
+            .line("let inner = grpc::Grpc::with_interceptor(inner, interceptor);")
+            .line("Self { inner }")
+            ;
+
         imp.new_fn("poll_ready")
             .vis("pub")
             .arg_mut_self()
@@ -81,6 +92,13 @@ impl ServiceGenerator {
             .line("self.inner.poll_ready()")
             ;
 
+        imp.new_fn("grpc_mut")
+            .vis("pub")
+            .arg_mut_self()
+            .ret("&mut grpc::Grpc<T>")
+            .line("&mut self.inner")
+            ;
+
         for method in &service.methods {
             let name = &method.name;
             let path = ::method_path(service, method);
@@ -95,7 +113,7 @@ impl ServiceGenerator {
 
             let mut request = codegen::Type::new("grpc::Request");
 
-            let req_body = match (method.client_streaming, method.server_streaming) {
+            match (method.client_streaming, method.server_streaming) {
                 (false, false) => {
                     let ret = format!(
                         "grpc::unary::ResponseFuture<{}, T::Future, T::ResponseBody>",
@@ -106,8 +124,6 @@ impl ServiceGenerator {
                     func.ret(ret)
                         .line("self.inner.unary(request, path)")
                         ;
-
-                    format!("grpc::unary::Once<{}>", input_type)
                 }
                 (false, true) => {
                     let ret = format!(
@@ -119,8 +135,6 @@ impl ServiceGenerator {
                     func.ret(ret)
                         .line("self.inner.server_streaming(request, path)")
                         ;
-
-                    format!("grpc::unary::Once<{}>", input_type)
                 }
                 (true, false) => {
                     let ret = format!(
@@ -130,11 +144,10 @@ impl ServiceGenerator {
                     request.generic("B");
 
                     func.generic("B")
+                        .bound("B", request_stream(&input_type))
                         .ret(ret)
                         .line("self.inner.client_streaming(request, path)")
                         ;
-
-                    "B".to_string()
                 }
                 (true, true) => {
                     let ret = format!(
@@ -144,16 +157,19 @@ impl ServiceGenerator {
                     request.generic("B");
 
                     func.generic("B")
+                        .bound("B", request_stream(&input_type))
                         .ret(ret)
                         .line("self.inner.streaming(request, path)")
                         ;
-
-                    "B".to_string()
                 }
-            };
+            }
 
-            func.arg("request", request)
-                .bound(&req_body, "grpc::Encodable<T::RequestBody>");
+            func.arg("request", request);
         }
     }
 }
+
+/// The bound on a stream of request messages of type `input_type`.
+fn request_stream(input_type: &str) -> String {
+    format!("futures::Stream<Item = {}, Error = grpc::Error> + Send + 'static", input_type)
+}
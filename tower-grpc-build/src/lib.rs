@@ -5,8 +5,10 @@ extern crate heck;
 mod client;
 mod server;
 
+use std::env;
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 
 use heck::CamelCase;
 
@@ -15,11 +17,15 @@ pub struct Config {
     prost: prost_build::Config,
     build_client: bool,
     build_server: bool,
+    file_descriptor_set: bool,
 }
 
 struct ServiceGenerator {
     client: Option<client::ServiceGenerator>,
     server: Option<server::ServiceGenerator>,
+    /// The file in `OUT_DIR` holding the serialized `FileDescriptorSet`, if
+    /// it is embedded.
+    file_descriptor_set: Option<String>,
     root_scope: codegen::Scope,
 }
 
@@ -35,6 +41,8 @@ impl Config {
 
             // Disable server code gen by default
             build_server: false,
+
+            file_descriptor_set: false,
         }
     }
 
@@ -55,6 +63,16 @@ impl Config {
         self
     }
 
+    /// Embed the serialized `FileDescriptorSet` of the compiled protos
+    ///
+    /// Each generated file gets a `FILE_DESCRIPTOR_SET: &[u8]` constant,
+    /// including imports, which can populate the server reflection service.
+    /// The set is written by the same `protoc` that `prost-build` runs.
+    pub fn file_descriptor_set(&mut self, enable: bool) -> &mut Self {
+        self.file_descriptor_set = enable;
+        self
+    }
+
     /// Generate code
     pub fn build<P>(&mut self, protos: &[P], includes: &[P]) -> io::Result<()>
     where P: AsRef<Path>,
@@ -70,10 +88,19 @@ impl Config {
             None
         };
 
+        let file_descriptor_set = if self.file_descriptor_set {
+            let file_name = file_descriptor_set_name(protos);
+            write_file_descriptor_set(&file_name, protos, includes)?;
+            Some(file_name)
+        } else {
+            None
+        };
+
         // Set or reset the service generator.
         self.prost.service_generator(Box::new(ServiceGenerator {
             client,
             server,
+            file_descriptor_set,
             root_scope: codegen::Scope::new(),
         }));
 
@@ -98,6 +125,14 @@ impl prost_build::ServiceGenerator for ServiceGenerator {
     }
 
     fn finalize(&mut self, buf: &mut String) {
+        if let Some(ref file_name) = self.file_descriptor_set {
+            self.root_scope.raw(&format!(
+                "/// The serialized `FileDescriptorSet` of the compiled protos.\n\
+                 pub const FILE_DESCRIPTOR_SET: &'static [u8] = \
+                 include_bytes!(concat!(env!(\"OUT_DIR\"), \"/{}\"));",
+                file_name));
+        }
+
         // Rather than outputting each service to the buffer as it's generated,
         // we generate the code in our root `codegen::Scope`, which is shared
         // between the generation of each service in the proto file. Unlike a
@@ -121,6 +156,63 @@ impl prost_build::ServiceGenerator for ServiceGenerator {
 
 // ===== utility fns =====
 
+/// The file in `OUT_DIR` that the `FileDescriptorSet` of `protos` is
+/// written to.
+///
+/// Named after the protos, so the sets written by separate `build` calls do
+/// not overwrite each other.
+fn file_descriptor_set_name<P>(protos: &[P]) -> String
+where P: AsRef<Path>,
+{
+    let names = protos.iter()
+        .map(|proto| {
+            proto.as_ref()
+                .with_extension("")
+                .to_string_lossy()
+                .replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+        })
+        .collect::<Vec<_>>();
+
+    format!("{}.file_descriptor_set.bin", names.join("-"))
+}
+
+/// Run `protoc` to write the `FileDescriptorSet` of `protos` to `file_name`
+/// in `OUT_DIR`.
+fn write_file_descriptor_set<P>(file_name: &str, protos: &[P], includes: &[P]) -> io::Result<()>
+where P: AsRef<Path>,
+{
+    let out_dir = env::var_os("OUT_DIR")
+        .map(PathBuf::from)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "OUT_DIR is not set"))?;
+
+    // The same `protoc` and well-known type includes that `prost-build`
+    // compiles with, so enabling the set cannot fail where `build` works.
+    let mut cmd = Command::new(prost_build::protoc());
+    cmd.arg("--include_imports")
+        .arg("-o")
+        .arg(out_dir.join(file_name));
+
+    for include in includes {
+        cmd.arg("-I").arg(include.as_ref());
+    }
+
+    cmd.arg("-I").arg(prost_build::protoc_include());
+
+    for proto in protos {
+        cmd.arg(proto.as_ref());
+    }
+
+    let output = cmd.output()?;
+
+    if !output.status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("protoc failed: {}", String::from_utf8_lossy(&output.stderr))));
+    }
+
+    Ok(())
+}
+
 fn method_path(service: &prost_build::Service, method: &prost_build::Method) -> String {
     format!("\"/{}.{}/{}\"",
             service.package,
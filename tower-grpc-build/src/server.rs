@@ -147,6 +147,7 @@ macro_rules! try_ready {
             .derive("Clone")
             .generic("T")
             .field(&lower_name, "T")
+            .field("grpc", "grpc::Grpc")
             ;
 
         {
@@ -160,7 +161,26 @@ macro_rules! try_ready {
                 .vis("pub")
                 .arg(&lower_name, "T")
                 .ret("Self")
-                .line(format!("Self {{ {} }}", lower_name))
+                .line(format!("Self {{ {}, grpc: grpc::Grpc::new() }}", lower_name))
+                ;
+
+            imp.new_fn("with_interceptor")
+                .vis("pub")
+                .generic("I")
+                .arg(&lower_name, "T")
+                .arg("interceptor", "I")
+                .ret("Self")
+                .bound("I", "grpc::Interceptor + 'static")
+                .line("let mut grpc = grpc::Grpc::new();")
+                .line("grpc.set_interceptor(interceptor);")
+                .line(format!("Self {{ {}, grpc }}", lower_name))
+                ;
+
+            imp.new_fn("grpc_mut")
+                .vis("pub")
+                .arg_mut_self()
+                .ret("&mut grpc::Grpc")
+                .line("&mut self.grpc")
                 ;
         }
 
@@ -187,12 +207,17 @@ macro_rules! try_ready {
         {
             let call = service_impl.new_fn("call")
                 .arg_mut_self()
-                .arg("request", "Self::Request")
+                .arg("mut request", "Self::Request")
                 .ret("Self::Future")
                 .line(&format!("use self::{}::Kind::*;", lower_name))
                 .line("")
                 ;
 
+            let mut intercept = codegen::Block::new(
+                "if let Err(status) = self.grpc.intercept(&mut request)");
+            intercept.line(&format!("return {}::ResponseFuture {{ kind: Err(status) }};", lower_name));
+            call.push_block(intercept);
+
             let mut route_block = codegen::Block::new("match request.uri().path()");
 
             for method in &service.methods {
@@ -211,28 +236,28 @@ macro_rules! try_ready {
                                 "let service = {}::methods::{}(self.{}.clone());",
                                 lower_name, &upper_name, lower_name));
 
-                        handle.line("let response = grpc::Grpc::unary(service, request);");
+                        handle.line("let response = self.grpc.unary(service, request);");
                     }
                     (false, true) => {
                         handle.line(&format!(
                                 "let service = {}::methods::{}(self.{}.clone());",
                                 lower_name, &upper_name, lower_name));
 
-                        handle.line("let response = grpc::Grpc::server_streaming(service, request);");
+                        handle.line("let response = self.grpc.server_streaming(service, request);");
                     }
                     (true, false) => {
                         handle.line(&format!(
                                 "let mut service = {}::methods::{}(self.{}.clone());",
                                 lower_name, &upper_name, lower_name));
 
-                        handle.line("let response = grpc::Grpc::client_streaming(&mut service, request);");
+                        handle.line("let response = self.grpc.client_streaming(&mut service, request);");
                     }
                     (true, true) => {
                         handle.line(&format!(
                                 "let mut service = {}::methods::{}(self.{}.clone());",
                                 lower_name, &upper_name, lower_name));
 
-                        handle.line("let response = grpc::Grpc::streaming(&mut service, request);");
+                        handle.line("let response = self.grpc.streaming(&mut service, request);");
                     }
                 }
 
@@ -270,6 +295,16 @@ macro_rules! try_ready {
             .ret("Self::Future")
             .line("futures::ok(self.clone())")
             ;
+
+        scope.new_impl(&name)
+            .generic("T")
+            .target_generic("T")
+            .impl_trait("grpc::NamedService")
+            .bound("T", &service.name)
+            .new_fn("service_name")
+            .ret("&'static str")
+            .line(&format!("\"{}.{}\"", service.package, service.proto_name))
+            ;
     }
 
     fn define_response_future(&self,
@@ -323,9 +358,15 @@ macro_rules! try_ready {
 
                 let mut err = codegen::Block::new("Err(ref status) =>");
 
+                // A status without a handler response, such as one from an
+                // interceptor, is still sent as a gRPC response.
                 err
                     .line("let body = ResponseBody { kind: Err(status.clone()) };")
-                    .line("Ok(grpc::Response::new(body).into_http().into())")
+                    .line("let mut response = grpc::Response::new(body).into_http();")
+                    .line("response.headers_mut().insert(")
+                    .line("    http::header::CONTENT_TYPE,")
+                    .line("    http::header::HeaderValue::from_static(\"application/grpc\"));")
+                    .line("Ok(response.into())")
                     ;
 
                 match_kind.push_block(err);
@@ -383,13 +424,15 @@ macro_rules! try_ready {
                 ));
         }
 
-        is_end_stream_block.line("Err(_) => true,");
+        // The status is sent in the trailers, so the stream must not end
+        // with the response headers.
+        is_end_stream_block.line("Err(_) => false,");
         poll_data_block.line("Err(_) => Ok(None.into()),");
 
         let mut poll_trailers_catch_all = codegen::Block::new("Err(ref status) =>");
         poll_trailers_catch_all
             .line("let mut map = http::HeaderMap::new();")
-            .line("map.insert(\"grpc-status\", status.to_header_value());")
+            .line("status.add_header(&mut map);")
             .line("Ok(Some(map).into())")
             ;
 
@@ -29,6 +29,7 @@ use futures::{future, Future, stream};
 use tokio_core::reactor;
 use tokio_core::net::TcpStream;
 use tower_grpc::Request;
+use tower_grpc::generic::compression::Compression;
 use tower_h2::client::Connection;
 
 use pb::SimpleRequest;
@@ -46,6 +47,13 @@ mod util;
 const LARGE_REQ_SIZE: usize = 271828;
 const LARGE_RSP_SIZE: i32 = 314159;
 
+/// Compression that sends gzip messages.
+fn gzip() -> Compression {
+    let mut compression = Compression::new();
+    compression.send(Some("gzip")).expect("gzip is registered");
+    compression
+}
+
 arg_enum!{
     #[derive(Debug, Copy, Clone)]
     #[allow(non_camel_case_types)]
@@ -307,6 +315,122 @@ impl Testcase {
                         })
                 )
             },
+            Testcase::timeout_on_sleeping_server => {
+                use futures::Stream;
+                use pb::StreamingOutputCallRequest;
+                use std::time::Duration;
+                use tower_grpc::Code;
+
+                let req = StreamingOutputCallRequest {
+                    payload: Some(util::client_payload(27182)),
+                    ..Default::default()
+                };
+                let mut req = Request::new(
+                    stream::iter_ok::<_, tower_grpc::Error>(vec![req]));
+                req.set_timeout(Duration::from_millis(1));
+                core.run(client.full_duplex_call(req)
+                    .map_err(|e| e.status())
+                    .and_then(|response| {
+                        response.into_inner()
+                            .collect()
+                            .map_err(|e| e.status())
+                    })
+                    .then(|result| {
+                        let code = result.as_ref().err().map(|status| status.code());
+                        let assertions = vec![
+                            test_assert!(
+                                "call must fail with DEADLINE_EXCEEDED",
+                                code == Some(Code::DEADLINE_EXCEEDED),
+                                format!("result={:?}", result)
+                            )
+                        ];
+                        future::ok::<Vec<TestAssertion>, Box<Error>>(assertions)
+                    }))
+            },
+            Testcase::cancel_after_begin => {
+                use futures::Stream;
+                use futures::sync::mpsc;
+                use pb::StreamingInputCallRequest;
+                use tower_grpc::Code;
+
+                // The request stream stays open until the call is canceled.
+                let (_tx, rx) = mpsc::unbounded::<StreamingInputCallRequest>();
+                let mut req = Request::new(rx.map_err(tower_grpc::Error::Inner));
+                let cancel = req.cancel_handle();
+
+                let response = client.streaming_input_call(req);
+                cancel.cancel();
+
+                core.run(response
+                    .then(|result| {
+                        let code = result.as_ref().err().map(|e| e.status().code());
+                        let assertions = vec![
+                            test_assert!(
+                                "call must fail with CANCELLED",
+                                code == Some(Code::CANCELED),
+                                format!("result={:?}", result)
+                            )
+                        ];
+                        future::ok::<Vec<TestAssertion>, Box<Error>>(assertions)
+                    }))
+            },
+            Testcase::cancel_after_first_response => {
+                use futures::Stream;
+                use futures::sync::mpsc;
+                use pb::{ResponseParameters, StreamingOutputCallRequest};
+                use tower_grpc::{Code, Status};
+
+                let (tx, rx) = mpsc::unbounded();
+                tx.unbounded_send(StreamingOutputCallRequest {
+                    response_parameters: vec![ResponseParameters {
+                        size: 31415,
+                        ..Default::default()
+                    }],
+                    payload: Some(util::client_payload(27182)),
+                    ..Default::default()
+                }).expect("send request");
+
+                let mut req = Request::new(rx.map_err(tower_grpc::Error::Inner));
+                let cancel = req.cancel_handle();
+
+                core.run(client.full_duplex_call(req)
+                    .map_err(|e| e.status())
+                    .and_then(|response| {
+                        response.into_inner()
+                            .into_future()
+                            .map_err(|(e, _)| e.status())
+                    })
+                    .and_then(move |(first, responses)| {
+                        cancel.cancel();
+
+                        responses.into_future().then(move |rest| {
+                            let code = rest.err().map(|(e, _)| e.status().code());
+                            Ok::<_, Status>((first.is_some(), code))
+                        })
+                    })
+                    .then(move |result| {
+                        // The request stream stays open until the call is
+                        // canceled.
+                        drop(tx);
+
+                        let (received, code) = match result {
+                            Ok(result) => result,
+                            Err(status) => (false, Some(status.code())),
+                        };
+                        let assertions = vec![
+                            test_assert!(
+                                "first response must be received",
+                                received
+                            ),
+                            test_assert!(
+                                "call must fail with CANCELLED",
+                                code == Some(Code::CANCELED),
+                                format!("code={:?}", code)
+                            )
+                        ];
+                        future::ok::<Vec<TestAssertion>, Box<Error>>(assertions)
+                    }))
+            },
             Testcase::compute_engine_creds
             | Testcase::jwt_token_creds
             | Testcase::oauth2_auth_token
@@ -315,14 +439,206 @@ impl Testcase {
                     "test case unimplemented: tower-grpc does not \
                      currently support gRPC authorization."
                 ),
-            Testcase::client_compressed_unary
-            | Testcase::server_compressed_unary
-            | Testcase::client_compressed_streaming
-            | Testcase::server_compressed_streaming =>
-                unimplemented!(
-                    "test case unimplemented: tower-grpc does not \
-                     currently support gRPC compression."
-                ),
+            Testcase::client_compressed_unary => {
+                use pb::BoolValue;
+                use tower_grpc::Code;
+                use tower_grpc::generic::compression::MessageCompression;
+
+                client.grpc_mut().set_compression(gzip());
+
+                let request = |expect_compressed: bool, compressed: bool| {
+                    let mut req = Request::new(SimpleRequest {
+                        response_type: pb::PayloadType::Compressable as i32,
+                        response_size: LARGE_RSP_SIZE,
+                        payload: Some(util::client_payload(LARGE_REQ_SIZE)),
+                        expect_compressed: Some(BoolValue { value: expect_compressed }),
+                        ..Default::default()
+                    });
+                    let handle = MessageCompression::new();
+                    handle.set_enabled(compressed);
+                    req.set_message_compression(handle);
+                    req
+                };
+
+                // Probe that the server checks the compressed flag.
+                let probe = core.run(client.unary_call(request(true, false)));
+                let code = probe.as_ref().err().map(|e| e.status().code());
+                let mut assertions = vec![
+                    test_assert!(
+                        "uncompressed request expecting compression must fail with INVALID_ARGUMENT",
+                        code == Some(Code::INVALID_ARGUMENT),
+                        format!("code={:?}", code)
+                    )
+                ];
+
+                for &compressed in &[true, false] {
+                    let result = core.run(client.unary_call(request(compressed, compressed)));
+                    assertions.push(test_assert!(
+                        "call must be successful",
+                        result.is_ok(),
+                        format!("compressed={}, result={:?}", compressed, result.as_ref().err())
+                    ));
+                    if let Ok(body) = result.map(|r| r.into_inner()) {
+                        let payload_len = body.payload.as_ref()
+                            .map(|p| p.body.len())
+                            .unwrap_or(0);
+
+                        assertions.push(test_assert!(
+                            "body must be 314159 bytes",
+                            payload_len == LARGE_RSP_SIZE as usize,
+                            format!("compressed={}, payload_len={}", compressed, payload_len)
+                        ));
+                    }
+                }
+                Ok(assertions)
+            },
+            Testcase::server_compressed_unary => {
+                use pb::BoolValue;
+
+                client.grpc_mut().set_compression(Compression::new());
+
+                let mut assertions = vec![];
+                for &compressed in &[true, false] {
+                    let req = SimpleRequest {
+                        response_type: pb::PayloadType::Compressable as i32,
+                        response_size: LARGE_RSP_SIZE,
+                        payload: Some(util::client_payload(LARGE_REQ_SIZE)),
+                        response_compressed: Some(BoolValue { value: compressed }),
+                        ..Default::default()
+                    };
+                    let result = core.run(client.unary_call(Request::new(req)));
+                    assertions.push(test_assert!(
+                        "call must be successful",
+                        result.is_ok(),
+                        format!("compressed={}, result={:?}", compressed, result.as_ref().err())
+                    ));
+                    if let Ok(body) = result.map(|r| r.into_inner()) {
+                        let payload_len = body.payload.as_ref()
+                            .map(|p| p.body.len())
+                            .unwrap_or(0);
+
+                        assertions.push(test_assert!(
+                            "body must be 314159 bytes",
+                            payload_len == LARGE_RSP_SIZE as usize,
+                            format!("compressed={}, payload_len={}", compressed, payload_len)
+                        ));
+                    }
+                }
+                Ok(assertions)
+            },
+            Testcase::client_compressed_streaming => {
+                use futures::Stream;
+                use pb::{BoolValue, StreamingInputCallRequest};
+                use tower_grpc::Code;
+                use tower_grpc::generic::compression::MessageCompression;
+
+                client.grpc_mut().set_compression(gzip());
+
+                // Probe that the server checks the compressed flag.
+                let probe = {
+                    let stream = stream::iter_ok::<_, tower_grpc::Error>(vec![
+                        StreamingInputCallRequest {
+                            payload: Some(util::client_payload(27182)),
+                            expect_compressed: Some(BoolValue { value: true }),
+                        },
+                    ]);
+                    let handle = MessageCompression::new();
+                    handle.set_enabled(false);
+                    let mut req = Request::new(stream);
+                    req.set_message_compression(handle);
+                    core.run(client.streaming_input_call(req))
+                };
+                let code = probe.as_ref().err().map(|e| e.status().code());
+                let mut assertions = vec![
+                    test_assert!(
+                        "uncompressed request expecting compression must fail with INVALID_ARGUMENT",
+                        code == Some(Code::INVALID_ARGUMENT),
+                        format!("code={:?}", code)
+                    )
+                ];
+
+                // The first message is compressed, the second is not.
+                let handle = MessageCompression::new();
+                let stream = {
+                    let handle = handle.clone();
+                    stream::iter_ok::<_, tower_grpc::Error>(vec![(true, 27182), (false, 45904)])
+                        .map(move |(compressed, size)| {
+                            handle.set_enabled(compressed);
+                            StreamingInputCallRequest {
+                                payload: Some(util::client_payload(size)),
+                                expect_compressed: Some(BoolValue { value: compressed }),
+                            }
+                        })
+                };
+                let mut req = Request::new(stream);
+                req.set_message_compression(handle);
+
+                let result = core.run(client.streaming_input_call(req));
+                assertions.push(test_assert!(
+                    "call must be successful",
+                    result.is_ok(),
+                    format!("result={:?}", result)
+                ));
+                if let Ok(response) = result.map(|r| r.into_inner()) {
+                    assertions.push(test_assert!(
+                        "aggregated payload size must be 73086 bytes",
+                        response.aggregated_payload_size == 73086,
+                        format!("aggregated_payload_size={:?}",
+                            response.aggregated_payload_size
+                        )));
+                }
+                Ok(assertions)
+            },
+            Testcase::server_compressed_streaming => {
+                use futures::Stream;
+                use pb::{BoolValue, ResponseParameters, StreamingOutputCallRequest};
+
+                client.grpc_mut().set_compression(Compression::new());
+
+                let req = StreamingOutputCallRequest {
+                    response_parameters: vec![
+                        ResponseParameters {
+                            size: 31415,
+                            compressed: Some(BoolValue { value: true }),
+                            ..Default::default()
+                        },
+                        ResponseParameters {
+                            size: 92653,
+                            compressed: Some(BoolValue { value: false }),
+                            ..Default::default()
+                        },
+                    ],
+                    ..Default::default()
+                };
+                core.run(client.streaming_output_call(Request::new(req))
+                    .map_err(|e| e.status())
+                    .and_then(|response| {
+                        response.into_inner()
+                            .collect()
+                            .map_err(|e| e.status())
+                    })
+                    .then(|result| {
+                        let mut assertions = vec![
+                            test_assert!(
+                                "call must be successful",
+                                result.is_ok(),
+                                format!("result={:?}", result.as_ref().err())
+                            )
+                        ];
+                        if let Ok(responses) = result {
+                            let sizes = responses.iter()
+                                .map(|r| r.payload.as_ref().map(|p| p.body.len()).unwrap_or(0))
+                                .collect::<Vec<_>>();
+
+                            assertions.push(test_assert!(
+                                "response payloads must be 31415 and 92653 bytes",
+                                sizes == [31415, 92653],
+                                format!("sizes={:?}", sizes)
+                            ));
+                        }
+                        future::ok::<Vec<TestAssertion>, Box<Error>>(assertions)
+                    }))
+            },
 
             _ => unimplemented!()
         }